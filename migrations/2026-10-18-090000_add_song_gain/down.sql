@@ -0,0 +1,13 @@
+-- SQLite can't drop columns, so the table is recreated without the gain
+CREATE TABLE songs_without_gain (
+                     id INTEGER PRIMARY KEY NOT NULL ,
+                     name VARCHAR NOT NULL,
+                     path VARCHAR NOT NULL,
+                     duration INTEGER NOT NULL,
+                     thumbnail_url VARCHAR NOT NULL,
+                     artists VARCHAR NOT NULL,
+                     nsfw INTEGER NOT NULL DEFAULT 1
+);
+INSERT INTO songs_without_gain SELECT id, name, path, duration, thumbnail_url, artists, nsfw FROM songs;
+DROP TABLE songs;
+ALTER TABLE songs_without_gain RENAME TO songs;
@@ -0,0 +1,2 @@
+-- Gain in dB bringing song to the target loudness, NULL until the song is analyzed
+ALTER TABLE songs ADD COLUMN gain REAL;
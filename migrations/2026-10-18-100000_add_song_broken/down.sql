@@ -0,0 +1,14 @@
+-- SQLite can't drop columns, so the table is recreated without the broken flag
+CREATE TABLE songs_without_broken (
+                     id INTEGER PRIMARY KEY NOT NULL ,
+                     name VARCHAR NOT NULL,
+                     path VARCHAR NOT NULL,
+                     duration INTEGER NOT NULL,
+                     thumbnail_url VARCHAR NOT NULL,
+                     artists VARCHAR NOT NULL,
+                     nsfw INTEGER NOT NULL DEFAULT 1,
+                     gain REAL
+);
+INSERT INTO songs_without_broken SELECT id, name, path, duration, thumbnail_url, artists, nsfw, gain FROM songs;
+DROP TABLE songs;
+ALTER TABLE songs_without_broken RENAME TO songs;
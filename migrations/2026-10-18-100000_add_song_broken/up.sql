@@ -0,0 +1,2 @@
+-- Songs the transmitter kept failing on, they aren't picked as random songs anymore
+ALTER TABLE songs ADD COLUMN broken INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Songs waiting in the queue, active one is on air and it's played again after restart
+CREATE TABLE queued_songs (
+                     uuid VARCHAR PRIMARY KEY NOT NULL,
+                     song_id INTEGER NOT NULL REFERENCES songs (id),
+                     requested_at TIMESTAMP NOT NULL,
+                     active INTEGER NOT NULL DEFAULT 0
+)
@@ -0,0 +1,10 @@
+-- SQLite can't drop columns, so the table is recreated without the requester
+CREATE TABLE queued_songs_without_requester (
+                     uuid VARCHAR PRIMARY KEY NOT NULL,
+                     song_id INTEGER NOT NULL REFERENCES songs (id),
+                     requested_at TIMESTAMP NOT NULL,
+                     active INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO queued_songs_without_requester SELECT uuid, song_id, requested_at, active FROM queued_songs;
+DROP TABLE queued_songs;
+ALTER TABLE queued_songs_without_requester RENAME TO queued_songs;
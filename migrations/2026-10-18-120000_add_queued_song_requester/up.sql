@@ -0,0 +1,2 @@
+-- Client that requested the song, used to share the queue fairly between clients
+ALTER TABLE queued_songs ADD COLUMN requested_by VARCHAR;
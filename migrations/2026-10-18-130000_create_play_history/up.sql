@@ -0,0 +1,9 @@
+-- Songs that were on air, ended_at is NULL while song is still on air
+CREATE TABLE play_history (
+                     id INTEGER PRIMARY KEY NOT NULL,
+                     song_id INTEGER NOT NULL REFERENCES songs (id),
+                     started_at TIMESTAMP NOT NULL,
+                     ended_at TIMESTAMP,
+                     skipped INTEGER NOT NULL DEFAULT 0,
+                     source VARCHAR NOT NULL
+)
@@ -0,0 +1,12 @@
+-- SQLite can't drop columns, so the table is recreated without the score
+CREATE TABLE play_history_without_score (
+                     id INTEGER PRIMARY KEY NOT NULL,
+                     song_id INTEGER NOT NULL REFERENCES songs (id),
+                     started_at TIMESTAMP NOT NULL,
+                     ended_at TIMESTAMP,
+                     skipped INTEGER NOT NULL DEFAULT 0,
+                     source VARCHAR NOT NULL
+);
+INSERT INTO play_history_without_score SELECT id, song_id, started_at, ended_at, skipped, source FROM play_history;
+DROP TABLE play_history;
+ALTER TABLE play_history_without_score RENAME TO play_history;
@@ -0,0 +1,2 @@
+-- Sum of the votes song got while it was queued, autoplay picks liked songs more often
+ALTER TABLE play_history ADD COLUMN score INTEGER NOT NULL DEFAULT 0;
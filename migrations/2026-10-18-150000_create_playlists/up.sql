@@ -0,0 +1,11 @@
+CREATE TABLE playlists (
+                     id INTEGER PRIMARY KEY NOT NULL,
+                     name VARCHAR NOT NULL
+);
+-- Songs of the playlist, they're played in the order of their positions
+CREATE TABLE playlist_songs (
+                     id INTEGER PRIMARY KEY NOT NULL,
+                     playlist_id INTEGER NOT NULL REFERENCES playlists (id),
+                     song_id INTEGER NOT NULL REFERENCES songs (id),
+                     position INTEGER NOT NULL
+);
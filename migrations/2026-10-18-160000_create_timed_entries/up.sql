@@ -0,0 +1,9 @@
+-- Song or playlist played at given local time, entries with weekdays are played every week
+CREATE TABLE timed_entries (
+                     id INTEGER PRIMARY KEY NOT NULL,
+                     song_id INTEGER REFERENCES songs (id),
+                     playlist_id INTEGER REFERENCES playlists (id),
+                     at TIMESTAMP NOT NULL,
+                     weekdays VARCHAR,
+                     interrupt INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,15 @@
+-- SQLite can't drop columns, so the table is recreated without the source url
+CREATE TABLE songs_without_source_url (
+                     id INTEGER PRIMARY KEY NOT NULL ,
+                     name VARCHAR NOT NULL,
+                     path VARCHAR NOT NULL,
+                     duration INTEGER NOT NULL,
+                     thumbnail_url VARCHAR NOT NULL,
+                     artists VARCHAR NOT NULL,
+                     nsfw INTEGER NOT NULL DEFAULT 1,
+                     gain REAL,
+                     broken INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO songs_without_source_url SELECT id, name, path, duration, thumbnail_url, artists, nsfw, gain, broken FROM songs;
+DROP TABLE songs;
+ALTER TABLE songs_without_source_url RENAME TO songs;
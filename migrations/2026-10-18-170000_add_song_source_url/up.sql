@@ -0,0 +1,2 @@
+-- Link the song was downloaded from, NULL for songs that were found by their name
+ALTER TABLE songs ADD COLUMN source_url VARCHAR;
@@ -0,0 +1,13 @@
+-- SQLite can't drop columns, so the table is recreated without the interrupted
+CREATE TABLE play_history_without_interrupted (
+                     id INTEGER PRIMARY KEY NOT NULL,
+                     song_id INTEGER NOT NULL REFERENCES songs (id),
+                     started_at TIMESTAMP NOT NULL,
+                     ended_at TIMESTAMP,
+                     skipped INTEGER NOT NULL DEFAULT 0,
+                     source VARCHAR NOT NULL,
+                     score INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO play_history_without_interrupted SELECT id, song_id, started_at, ended_at, skipped, source, score FROM play_history;
+DROP TABLE play_history;
+ALTER TABLE play_history_without_interrupted RENAME TO play_history;
@@ -0,0 +1,2 @@
+-- Song cut off by a timed entry wasn't skipped by the clients
+ALTER TABLE play_history ADD COLUMN interrupted INTEGER NOT NULL DEFAULT 0;
@@ -6,5 +6,52 @@ CREATE TABLE songs
     duration INTEGER NOT NULL,
     thumbnail_url VARCHAR NOT NULL,
     artists VARCHAR NOT NULL,
-    nsfw INTEGER NOT NULL DEFAULT 1
-)
\ No newline at end of file
+    nsfw INTEGER NOT NULL DEFAULT 1,
+    gain REAL,
+    broken INTEGER NOT NULL DEFAULT 0,
+    source_url VARCHAR
+);
+
+CREATE TABLE queued_songs
+(
+    uuid VARCHAR PRIMARY KEY NOT NULL,
+    song_id INTEGER NOT NULL REFERENCES songs (id),
+    requested_at TIMESTAMP NOT NULL,
+    active INTEGER NOT NULL DEFAULT 0,
+    requested_by VARCHAR
+);
+CREATE TABLE play_history
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    song_id INTEGER NOT NULL REFERENCES songs (id),
+    started_at TIMESTAMP NOT NULL,
+    ended_at TIMESTAMP,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    source VARCHAR NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    interrupted BOOLEAN NOT NULL DEFAULT 0
+);
+
+CREATE TABLE playlists
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name VARCHAR NOT NULL
+);
+
+CREATE TABLE playlist_songs
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    playlist_id INTEGER NOT NULL REFERENCES playlists (id),
+    song_id INTEGER NOT NULL REFERENCES songs (id),
+    position INTEGER NOT NULL
+);
+
+CREATE TABLE timed_entries
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    song_id INTEGER REFERENCES songs (id),
+    playlist_id INTEGER REFERENCES playlists (id),
+    at TIMESTAMP NOT NULL,
+    weekdays VARCHAR,
+    interrupt INTEGER NOT NULL DEFAULT 0
+);
\ No newline at end of file
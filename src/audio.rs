@@ -0,0 +1,149 @@
+use std::process::{Command, Stdio};
+
+/// Loudness every song is brought to, in LUFS, same as the ReplayGain 2.0 reference level.
+const TARGET_LOUDNESS: f32 = -18.0;
+/// Gain in dB is never bigger than this, so quiet intros and silence aren't blown up into noise.
+const MAX_GAIN: f32 = 12.0;
+
+/// Command decoding song via ffmpeg, starting at given second, with given gain in dB applied.
+/// Decoded wav is written to stdout, so it can be piped straight into the transmitter.
+pub fn decoder(path: &str, offset: i32, gain: f32) -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-ss")
+        .arg(offset.to_string())
+        .arg("-i")
+        .arg(path);
+    apply_gain(&mut command, gain);
+    command
+        .arg("-f")
+        .arg("wav")
+        .arg("-")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    command
+}
+
+/// Sample rate of the audio mixed by the radio's stream.
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Number of channels of the audio mixed by the radio's stream.
+pub const CHANNELS: u32 = 2;
+
+/// Command decoding song via ffmpeg into raw 16 bit samples, starting at given second,
+/// with given gain in dB applied.
+/// Every song is resampled to the same format, so they can be mixed together.
+pub fn pcm_decoder(path: &str, offset: i32, gain: f32) -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-ss")
+        .arg(offset.to_string())
+        .arg("-i")
+        .arg(path);
+    apply_gain(&mut command, gain);
+    command
+        .arg("-f")
+        .arg("s16le")
+        .arg("-ar")
+        .arg(SAMPLE_RATE.to_string())
+        .arg("-ac")
+        .arg(CHANNELS.to_string())
+        .arg("-")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    command
+}
+
+/// Adds volume filter to the ffmpeg command, unless there's nothing to change.
+fn apply_gain(command: &mut Command, gain: f32) {
+    if gain != 0.0 {
+        command.arg("-af").arg(format!("volume={}dB", gain));
+    }
+}
+
+/// Measures integrated loudness of the song (EBU R128) and returns gain in dB
+/// that brings it to the target loudness.
+/// Goes through the whole song, so it takes a while.
+pub fn measure_gain(path: &str) -> Result<f32, ()> {
+    let output = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-i")
+        .arg(path)
+        .arg("-af")
+        .arg("ebur128")
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| println!("couldn't run ffmpeg - {}", e))?;
+    if !output.status.success() {
+        println!(
+            "couldn't measure loudness of {} - {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(());
+    }
+    // summary printed at the end contains line "I: <loudness> LUFS"
+    let loudness = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("I:"))
+        .filter_map(|value| value.split_whitespace().next()?.parse::<f32>().ok())
+        .next_back()
+        .ok_or(())?;
+    Ok((TARGET_LOUDNESS - loudness).clamp(-MAX_GAIN, MAX_GAIN))
+}
+
+/// Reads duration of the song in seconds via ffprobe, for songs whose site didn't tell it.
+pub fn probe_duration(path: &str) -> Result<f64, ()> {
+    let output = Command::new("ffprobe")
+        .arg("-v")
+        .arg("error")
+        .arg("-show_entries")
+        .arg("format=duration")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1:nokey=1")
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| println!("couldn't run ffprobe - {}", e))?;
+    if !output.status.success() {
+        println!(
+            "couldn't read duration of {} - {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(());
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ())
+}
+
+/// Header of wav that never ends, written before raw samples piped into the transmitter.
+/// Unknown sizes are set to the maximum, just like sox does when it writes wav into a pipe.
+pub fn stream_wav_header() -> Vec<u8> {
+    let bytes_per_frame = CHANNELS * 2;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&(CHANNELS as u16).to_le_bytes());
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * bytes_per_frame).to_le_bytes());
+    header.extend_from_slice(&(bytes_per_frame as u16).to_le_bytes());
+    // bits per sample
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
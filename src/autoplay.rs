@@ -0,0 +1,284 @@
+use super::history::HistoryEntry;
+use super::song::Song;
+use chrono::{Duration, NaiveDateTime};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+/// How a song is picked when the queue is empty.
+pub enum AutoplayStrategy {
+    /// Every song has the same chance.
+    #[default]
+    Random,
+    /// Songs with more likes and fewer plays have a better chance.
+    Weighted,
+    /// Songs by artists of the song played last go first, weighted like above.
+    SameArtist,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+/// Configuration of the autoplay.
+pub struct AutoplayConfig {
+    pub strategy: AutoplayStrategy,
+    // song isn't picked again until this many other songs were played
+    pub no_repeat_songs: usize,
+    // song isn't picked again until this many hours passed since it was played
+    pub no_repeat_hours: u32,
+    // songs are picked only from this playlist if it's set
+    #[serde(default)]
+    pub playlist: Option<i32>,
+}
+
+impl Default for AutoplayConfig {
+    fn default() -> Self {
+        AutoplayConfig {
+            strategy: AutoplayStrategy::default(),
+            no_repeat_songs: 10,
+            no_repeat_hours: 1,
+            playlist: None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+/// What is known about the song from the history.
+pub struct Stats {
+    pub plays: u32,
+    // sum of the votes song got while it was queued
+    pub likes: i32,
+}
+
+impl Stats {
+    /// Liked songs are picked more often, songs that were played a lot less often.
+    fn weight(&self) -> f64 {
+        f64::from(1 + self.likes.max(0)) / f64::from(1 + self.plays)
+    }
+}
+
+/// Picks songs to play when the queue is empty.
+/// It only decides based on the songs and history it's given, so it doesn't depend on any actor.
+pub struct Autoplay<'a> {
+    config: &'a AutoplayConfig,
+    // songs that were on air recently, the most recent ones go first
+    // it has to cover `no_repeat_songs` and `no_repeat_hours`, older songs don't matter
+    history: &'a [HistoryEntry],
+    // plays and likes of every song that was ever on air
+    stats: &'a HashMap<i32, Stats>,
+    now: NaiveDateTime,
+}
+
+impl<'a> Autoplay<'a> {
+    pub fn new(
+        config: &'a AutoplayConfig,
+        history: &'a [HistoryEntry],
+        stats: &'a HashMap<i32, Stats>,
+        now: NaiveDateTime,
+    ) -> Self {
+        Autoplay {
+            config,
+            history,
+            stats,
+            now,
+        }
+    }
+
+    /// Picks one of given songs, None if there are none.
+    pub fn pick<R: Rng>(&self, songs: Vec<Song>, rng: &mut R) -> Option<Song> {
+        let mut songs = self.without_repeats(songs);
+        if self.config.strategy == AutoplayStrategy::SameArtist {
+            songs = self.by_last_artist(songs);
+        }
+        let weights: Vec<f64> = match self.config.strategy {
+            AutoplayStrategy::Random => songs.iter().map(|_| 1.0).collect(),
+            AutoplayStrategy::Weighted | AutoplayStrategy::SameArtist => songs
+                .iter()
+                .map(|song| self.stats.get(&song.id).map_or(1.0, Stats::weight))
+                .collect(),
+        };
+        let index = WeightedIndex::new(&weights).ok()?.sample(rng);
+        Some(songs.swap_remove(index))
+    }
+
+    /// Leaves out songs that were played recently.
+    /// If all of them were, only the song played last is left out, so it's never played twice in a row.
+    fn without_repeats(&self, songs: Vec<Song>) -> Vec<Song> {
+        let since = self.now - Duration::hours(i64::from(self.config.no_repeat_hours));
+        let recent: Vec<i32> = self
+            .history
+            .iter()
+            .enumerate()
+            .take_while(|(index, entry)| {
+                *index < self.config.no_repeat_songs || entry.played_song.started_at > since
+            })
+            .map(|(_, entry)| entry.song.id)
+            .collect();
+        let (fresh, repeated): (Vec<Song>, Vec<Song>) = songs
+            .into_iter()
+            .partition(|song| !recent.contains(&song.id));
+        if !fresh.is_empty() {
+            return fresh;
+        }
+        let last_song_id = self.history.first().map(|entry| entry.song.id);
+        let (repeated, last): (Vec<Song>, Vec<Song>) = repeated
+            .into_iter()
+            .partition(|song| Some(song.id) != last_song_id);
+        if repeated.is_empty() {
+            last
+        } else {
+            repeated
+        }
+    }
+
+    /// Leaves songs that share an artist with the song played last, all of them if there are none.
+    fn by_last_artist(&self, songs: Vec<Song>) -> Vec<Song> {
+        let last_song = match self.history.first() {
+            Some(entry) => &entry.song,
+            None => return songs,
+        };
+        let last_artists = artists(last_song);
+        let (same_artist, others): (Vec<Song>, Vec<Song>) = songs.into_iter().partition(|song| {
+            song.id != last_song.id
+                && artists(song)
+                    .iter()
+                    .any(|artist| last_artists.contains(artist))
+        });
+        if same_artist.is_empty() {
+            others
+        } else {
+            same_artist
+        }
+    }
+}
+
+/// Artists of the song, e.g "Daft Punk, Pharrell Williams" are two of them.
+fn artists(song: &Song) -> Vec<String> {
+    song.artists
+        .split(&[',', '&'][..])
+        .map(|artist| artist.trim().to_lowercase())
+        .filter(|artist| !artist.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::PlayedSong;
+    use crate::song::test_helpers::song;
+    use chrono::NaiveDate;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd(2026, 10, 18).and_hms(12, 0, 0)
+    }
+
+    /// Song that went on air given number of minutes ago.
+    fn played(song: Song, minutes_ago: i64) -> HistoryEntry {
+        let played_song = PlayedSong {
+            id: 0,
+            song_id: song.id,
+            started_at: now() - Duration::minutes(minutes_ago),
+            ended_at: None,
+            skipped: false,
+            source: "autoplay".to_owned(),
+            score: 0,
+            interrupted: false,
+        };
+        HistoryEntry::from((played_song, song))
+    }
+
+    fn config(
+        strategy: AutoplayStrategy,
+        no_repeat_songs: usize,
+        no_repeat_hours: u32,
+    ) -> AutoplayConfig {
+        AutoplayConfig {
+            strategy,
+            no_repeat_songs,
+            no_repeat_hours,
+            playlist: None,
+        }
+    }
+
+    /// Ids of the songs picked in given number of tries.
+    fn picks(autoplay: &Autoplay, songs: &[Song], tries: usize) -> Vec<i32> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..tries)
+            .filter_map(|_| autoplay.pick(songs.to_vec(), &mut rng))
+            .map(|song| song.id)
+            .collect()
+    }
+
+    #[test]
+    fn doesnt_repeat_last_songs() {
+        let config = config(AutoplayStrategy::Random, 2, 0);
+        let history = vec![
+            played(song(3, "a"), 600),
+            played(song(2, "a"), 700),
+            played(song(1, "a"), 800),
+        ];
+        let stats = HashMap::new();
+        let autoplay = Autoplay::new(&config, &history, &stats, now());
+        let songs = vec![song(1, "a"), song(2, "a"), song(3, "a")];
+        assert!(picks(&autoplay, &songs, 20).iter().all(|id| *id == 1));
+    }
+
+    #[test]
+    fn doesnt_repeat_songs_played_in_last_hours() {
+        let config = config(AutoplayStrategy::Random, 0, 1);
+        let history = vec![played(song(2, "a"), 10), played(song(1, "a"), 120)];
+        let stats = HashMap::new();
+        let autoplay = Autoplay::new(&config, &history, &stats, now());
+        let songs = vec![song(1, "a"), song(2, "a")];
+        assert!(picks(&autoplay, &songs, 20).iter().all(|id| *id == 1));
+    }
+
+    #[test]
+    fn picks_liked_songs_played_less_more_often() {
+        let config = config(AutoplayStrategy::Weighted, 0, 0);
+        let mut stats = HashMap::new();
+        stats.insert(1, Stats { plays: 9, likes: 0 });
+        stats.insert(2, Stats { plays: 0, likes: 9 });
+        let autoplay = Autoplay::new(&config, &[], &stats, now());
+        let songs = vec![song(1, "a"), song(2, "a")];
+        let picks = picks(&autoplay, &songs, 1000);
+        let liked = picks.iter().filter(|id| **id == 2).count();
+        assert!(liked > 900, "liked song was picked {} times", liked);
+    }
+
+    #[test]
+    fn same_artist_continues_with_artist_of_last_song() {
+        let config = config(AutoplayStrategy::SameArtist, 1, 0);
+        let history = vec![played(song(1, "Daft Punk"), 600)];
+        let stats = HashMap::new();
+        let autoplay = Autoplay::new(&config, &history, &stats, now());
+        let songs = vec![
+            song(1, "Daft Punk"),
+            song(2, "daft punk, Pharrell Williams"),
+            song(3, "Justice"),
+        ];
+        assert!(picks(&autoplay, &songs, 20).iter().all(|id| *id == 2));
+        // other artists are picked if the last one has no other songs
+        let songs = vec![song(1, "Daft Punk"), song(3, "Justice")];
+        assert!(picks(&autoplay, &songs, 20).iter().all(|id| *id == 3));
+    }
+
+    #[test]
+    fn falls_back_to_repeats_when_every_song_was_played_recently() {
+        let config = config(AutoplayStrategy::Random, 10, 1);
+        let history = vec![played(song(2, "a"), 5), played(song(1, "a"), 10)];
+        let stats = HashMap::new();
+        let autoplay = Autoplay::new(&config, &history, &stats, now());
+        // song played last isn't played twice in a row
+        let songs = vec![song(1, "a"), song(2, "a")];
+        assert!(picks(&autoplay, &songs, 20).iter().all(|id| *id == 1));
+        // unless it's the only one
+        assert_eq!(picks(&autoplay, &[song(2, "a")], 1), vec![2]);
+        assert!(autoplay
+            .pick(Vec::new(), &mut StdRng::seed_from_u64(7))
+            .is_none());
+    }
+}
@@ -67,6 +67,20 @@ impl Handler<RegisterWS> for ClientPublisher {
     }
 }
 
+/// Get number of connected websockets.
+pub struct GetClientCount;
+
+impl Message for GetClientCount {
+    type Result = usize;
+}
+
+impl Handler<GetClientCount> for ClientPublisher {
+    type Result = usize;
+    fn handle(&mut self, _msg: GetClientCount, _ctx: &mut Self::Context) -> Self::Result {
+        self.websockets.len()
+    }
+}
+
 /// Sends message to every client in the vector of available connections.
 impl<T> Handler<UserMessage<T>> for ClientPublisher
 where
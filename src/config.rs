@@ -1,25 +1,190 @@
-use super::radio::{GetFrequency, SetFrequency};
+use super::autoplay::AutoplayConfig;
+use super::client_publisher::ClientPublisher;
+use super::download::{Downloads, SetDownloadLimit};
+use super::radio::{GetConfig, SetConfig};
 use super::responses::get_standard_success_response;
+use super::schedule::Schedule;
+use super::song::Song;
+use super::song_queue::{QueueOrder, RequestLimits, SetQueueConfig};
 use super::system::AppState;
+use super::web_socket::UserMessage;
+use actix::SystemService;
 use actix_web::{AsyncResponder, Error as AWError, FutureResponse, HttpResponse, Json, State};
 use futures::future::Future;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+/// RDS allows at most 8 characters of station name.
+const MAX_STATION_NAME_LENGTH: usize = 8;
+/// RDS allows at most 64 characters of radio text.
+const MAX_RADIO_TEXT_LENGTH: usize = 64;
+/// Longest crossfade in seconds.
+const MAX_CROSSFADE: i32 = 15;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 /// Configuration used mostly for radio commands.
 pub struct Config {
     pub frequency: f32,
+    // RDS station name
+    pub station_name: String,
+    // RDS programme identification code, 4 hexadecimal digits
+    pub pi_code: String,
+    // RDS radio text, {name} and {artists} are replaced with data of the song on air
+    pub radio_text: String,
+    // seconds during which the ending song is mixed with the next one, 0 disables crossfade
+    pub crossfade: i32,
+    // whether songs are played one after another without any silence between them
+    pub gapless: bool,
+    // when the radio is on air, it's on air all the time if there are no windows
+    pub schedule: Schedule,
+    // order in which scheduled songs are played
+    pub queue_order: QueueOrder,
+    // part of connected clients that has to vote for skipping the song on air
+    pub skip_threshold: f32,
+    // how songs are picked when the queue is empty
+    pub autoplay: AutoplayConfig,
+    // how many songs clients can request and which of them are rejected
+    pub request_limits: RequestLimits,
+    // how many songs can be downloaded at once
+    pub max_downloads: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            frequency: 104.1,
+            station_name: "HomeFM".to_owned(),
+            pi_code: "1234".to_owned(),
+            radio_text: "{name} - {artists}".to_owned(),
+            crossfade: 0,
+            gapless: false,
+            schedule: Schedule::default(),
+            queue_order: QueueOrder::default(),
+            skip_threshold: 0.5,
+            autoplay: AutoplayConfig::default(),
+            request_limits: RequestLimits::default(),
+            max_downloads: 2,
+        }
+    }
+}
+
+impl Config {
+    /// Checks whether config can be used by the radio, returns reason why not otherwise.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.station_name.chars().count() > MAX_STATION_NAME_LENGTH {
+            return Err(format!(
+                "station name can't be longer than {} characters",
+                MAX_STATION_NAME_LENGTH
+            ));
+        }
+        if self.pi_code.len() != 4 || !self.pi_code.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("PI code has to be 4 hexadecimal digits".to_owned());
+        }
+        if self.crossfade < 0 || self.crossfade > MAX_CROSSFADE {
+            return Err(format!(
+                "crossfade has to be between 0 and {} seconds",
+                MAX_CROSSFADE
+            ));
+        }
+        if self.skip_threshold < 0.0 || self.skip_threshold > 1.0 {
+            return Err("skip threshold has to be between 0 and 1".to_owned());
+        }
+        if self.request_limits.max_songs_per_client == Some(0)
+            || self.request_limits.max_queue_duration == Some(0)
+        {
+            return Err("request limits have to be greater than 0".to_owned());
+        }
+        if self.max_downloads == 0 {
+            return Err("at least one song has to be downloaded at once".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Whether songs are mixed into a continuous stream instead of being transmitted one by one.
+    /// Crossfade needs both songs on air at once, so it's streamed even if gapless is off.
+    pub fn is_streamed(&self) -> bool {
+        self.gapless || self.crossfade > 0
+    }
+
+    /// Radio text for given song.
+    pub fn render_radio_text(&self, song: &Song) -> String {
+        self.radio_text
+            .replace("{name}", &song.name)
+            .replace("{artists}", &song.artists)
+            .chars()
+            .take(MAX_RADIO_TEXT_LENGTH)
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+/// Changes to the config, fields that are missing are left as they were.
+pub struct ConfigUpdate {
+    pub frequency: Option<f32>,
+    pub station_name: Option<String>,
+    pub pi_code: Option<String>,
+    pub radio_text: Option<String>,
+    pub crossfade: Option<i32>,
+    pub gapless: Option<bool>,
+    pub schedule: Option<Schedule>,
+    pub queue_order: Option<QueueOrder>,
+    pub skip_threshold: Option<f32>,
+    pub autoplay: Option<AutoplayConfig>,
+    pub request_limits: Option<RequestLimits>,
+    pub max_downloads: Option<usize>,
+}
+
+impl ConfigUpdate {
+    /// Returns given config with the changes applied.
+    pub fn apply(self, config: Config) -> Config {
+        Config {
+            frequency: self.frequency.unwrap_or(config.frequency),
+            station_name: self.station_name.unwrap_or(config.station_name),
+            pi_code: self.pi_code.unwrap_or(config.pi_code),
+            radio_text: self.radio_text.unwrap_or(config.radio_text),
+            crossfade: self.crossfade.unwrap_or(config.crossfade),
+            gapless: self.gapless.unwrap_or(config.gapless),
+            schedule: self.schedule.unwrap_or(config.schedule),
+            queue_order: self.queue_order.unwrap_or(config.queue_order),
+            skip_threshold: self.skip_threshold.unwrap_or(config.skip_threshold),
+            autoplay: self.autoplay.unwrap_or(config.autoplay),
+            request_limits: self.request_limits.unwrap_or(config.request_limits),
+            max_downloads: self.max_downloads.unwrap_or(config.max_downloads),
+        }
+    }
 }
 
 /// PUT /config
-pub fn update_config(config: Json<Config>, state: State<AppState>) -> FutureResponse<HttpResponse> {
+/// Changes are applied to the song on air and the queue right away and broadcasted to all of the clients.
+pub fn update_config(
+    update: Json<ConfigUpdate>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    let queue_handler = state.queue_handler.clone();
     state
         .radio
-        .send(SetFrequency {
-            frequency: config.frequency,
+        .send(SetConfig {
+            update: update.into_inner(),
         })
-        .and_then(|_| Ok(HttpResponse::Ok().json(get_standard_success_response())))
         .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(move |config| {
+            queue_handler.do_send(SetQueueConfig {
+                order: config.queue_order,
+                skip_threshold: config.skip_threshold,
+                autoplay: config.autoplay.clone(),
+                request_limits: config.request_limits.clone(),
+            });
+            Downloads::from_registry().do_send(SetDownloadLimit {
+                max_downloads: config.max_downloads,
+            });
+            let response = UserMessage::<Config> {
+                success: true,
+                action: "config_changed".to_owned(),
+                value: config,
+            };
+            ClientPublisher::from_registry().do_send(response);
+            HttpResponse::Ok().json(get_standard_success_response())
+        })
         .responder()
 }
 
@@ -27,8 +192,8 @@ pub fn update_config(config: Json<Config>, state: State<AppState>) -> FutureResp
 pub fn get_config(state: State<AppState>) -> FutureResponse<HttpResponse> {
     state
         .radio
-        .send(GetFrequency {})
-        .and_then(|frequency| Ok(HttpResponse::Ok().json(Config { frequency })))
+        .send(GetConfig {})
+        .and_then(|config| Ok(HttpResponse::Ok().json(config)))
         .from_err()
         .responder()
 }
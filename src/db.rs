@@ -1,10 +1,20 @@
-use super::schema::songs;
+use super::schema::{play_history, playlist_songs, playlists, queued_songs, songs, timed_entries};
+use crate::autoplay::{Autoplay, AutoplayConfig, Stats};
+use crate::history::{HistoryEntry, NewPlayedSong, PlayedSong, SongEnd};
+use crate::playlist::{
+    validate_name, NewPlaylist, NewPlaylistSong, Playlist, PlaylistError, PlaylistRequest,
+    PlaylistUpdate, PlaylistWithSongs,
+};
 use crate::song::{NewSong, Song};
+use crate::song_queue::QueuedSong;
+use crate::timed::{NewTimedEntry, TimedEntry, TimedEntryError, TimedEntryRow};
 use actix::{Actor, Context, Handler, Message};
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager, Pool, PooledConnection};
 use diesel::result::Error as DieselError;
 use diesel::sqlite::SqliteConnection;
+use std::collections::HashMap;
 
 pub type Conn = SqliteConnection;
 pub type SqlPool = Pool<ConnectionManager<Conn>>;
@@ -38,20 +48,6 @@ impl DBExecutor {
     }
 }
 
-/// Get random song from db with nsfw set to false.
-pub struct GetRandomSong;
-impl Message for GetRandomSong {
-    type Result = Result<Song, DieselError>;
-}
-
-impl Handler<GetRandomSong> for DBExecutor {
-    type Result = Result<Song, DieselError>;
-
-    fn handle(&mut self, msg: GetRandomSong, ctx: &mut Self::Context) -> Self::Result {
-        get_random_song(&self.get_conn())
-    }
-}
-
 /// Check if song is already saved in the database.
 /// If song exists then it is returned, otherwise return DieselError.
 pub struct CheckSongExistence {
@@ -70,6 +66,22 @@ impl Handler<CheckSongExistence> for DBExecutor {
     }
 }
 
+/// Get song that was downloaded from given url.
+pub struct GetSongBySourceUrl {
+    pub url: String,
+}
+
+impl Message for GetSongBySourceUrl {
+    type Result = Result<Song, DieselError>;
+}
+
+impl Handler<GetSongBySourceUrl> for DBExecutor {
+    type Result = Result<Song, DieselError>;
+    fn handle(&mut self, msg: GetSongBySourceUrl, _ctx: &mut Self::Context) -> Self::Result {
+        get_song_by_source_url(&self.get_conn(), msg.url)
+    }
+}
+
 /// Save new song in database.
 pub struct SaveSong {
     pub song: NewSong,
@@ -135,22 +147,431 @@ impl Handler<DeleteSong> for DBExecutor {
         delete_song(&self.get_conn(), msg.song_id)
     }
 }
-/// Returns random song from db with nsfw set to false.
-fn get_random_song(conn: &PooledConn) -> Result<Song, DieselError> {
-    use super::schema::songs::dsl::nsfw;
+/// Get all songs whose loudness wasn't measured yet.
+pub struct GetSongsWithoutGain;
+impl Message for GetSongsWithoutGain {
+    type Result = Result<Vec<Song>, DieselError>;
+}
 
-    no_arg_sql_function!(RANDOM, (), "Represents the sql RANDOM() function");
-    songs::table
-        .filter(nsfw.eq(false))
-        .order(RANDOM)
-        .limit(1)
-        .first::<Song>(conn)
+impl Handler<GetSongsWithoutGain> for DBExecutor {
+    type Result = Result<Vec<Song>, DieselError>;
+
+    fn handle(&mut self, _msg: GetSongsWithoutGain, _ctx: &mut Self::Context) -> Self::Result {
+        get_songs_without_gain(&self.get_conn())
+    }
+}
+
+/// Set gain of song with given id.
+pub struct SetSongGain {
+    pub song_id: i32,
+    pub gain: f32,
+}
+
+impl Message for SetSongGain {
+    type Result = Result<Song, DieselError>;
+}
+
+impl Handler<SetSongGain> for DBExecutor {
+    type Result = Result<Song, DieselError>;
+
+    fn handle(&mut self, msg: SetSongGain, _ctx: &mut Self::Context) -> Self::Result {
+        set_song_gain(&self.get_conn(), msg.song_id, msg.gain)
+    }
+}
+
+/// Mark song with given id as broken, so it isn't played as a random song anymore.
+pub struct MarkSongBroken {
+    pub song_id: i32,
+}
+
+impl Message for MarkSongBroken {
+    type Result = Result<Song, DieselError>;
+}
+
+impl Handler<MarkSongBroken> for DBExecutor {
+    type Result = Result<Song, DieselError>;
+
+    fn handle(&mut self, msg: MarkSongBroken, _ctx: &mut Self::Context) -> Self::Result {
+        mark_song_broken(&self.get_conn(), msg.song_id)
+    }
+}
+
+/// Get songs waiting in the queue along with the active one.
+pub struct GetQueuedSongs;
+
+impl Message for GetQueuedSongs {
+    type Result = Result<Vec<(QueuedSong, Song)>, DieselError>;
+}
+
+impl Handler<GetQueuedSongs> for DBExecutor {
+    type Result = Result<Vec<(QueuedSong, Song)>, DieselError>;
+
+    fn handle(&mut self, _msg: GetQueuedSongs, _ctx: &mut Self::Context) -> Self::Result {
+        get_queued_songs(&self.get_conn())
+    }
+}
+
+/// Save song added to the queue.
+pub struct SaveQueuedSong {
+    pub song: QueuedSong,
+}
+
+impl Message for SaveQueuedSong {
+    type Result = Result<(), DieselError>;
+}
+
+impl Handler<SaveQueuedSong> for DBExecutor {
+    type Result = Result<(), DieselError>;
+
+    fn handle(&mut self, msg: SaveQueuedSong, _ctx: &mut Self::Context) -> Self::Result {
+        save_queued_song(&self.get_conn(), &msg.song)
+    }
+}
+
+/// Delete song with given uuid from the queue.
+pub struct DeleteQueuedSong {
+    pub uuid: String,
+}
+
+impl Message for DeleteQueuedSong {
+    type Result = Result<(), DieselError>;
+}
+
+impl Handler<DeleteQueuedSong> for DBExecutor {
+    type Result = Result<(), DieselError>;
+
+    fn handle(&mut self, msg: DeleteQueuedSong, _ctx: &mut Self::Context) -> Self::Result {
+        delete_queued_song(&self.get_conn(), &msg.uuid)
+    }
+}
+
+/// Mark song with given uuid as the active one, song that was active before is dropped.
+/// None if song that isn't from the queue is played.
+pub struct SetActiveQueuedSong {
+    pub uuid: Option<String>,
+}
+
+impl Message for SetActiveQueuedSong {
+    type Result = Result<(), DieselError>;
+}
+
+impl Handler<SetActiveQueuedSong> for DBExecutor {
+    type Result = Result<(), DieselError>;
+
+    fn handle(&mut self, msg: SetActiveQueuedSong, _ctx: &mut Self::Context) -> Self::Result {
+        set_active_queued_song(&self.get_conn(), msg.uuid)
+    }
+}
+
+/// Get all of the playlists along with their songs.
+pub struct GetPlaylists;
+
+impl Message for GetPlaylists {
+    type Result = Result<Vec<PlaylistWithSongs>, PlaylistError>;
+}
+
+impl Handler<GetPlaylists> for DBExecutor {
+    type Result = Result<Vec<PlaylistWithSongs>, PlaylistError>;
+
+    fn handle(&mut self, _msg: GetPlaylists, _ctx: &mut Self::Context) -> Self::Result {
+        get_playlists(&self.get_conn())
+    }
+}
+
+/// Get playlist with given id along with its songs.
+pub struct GetPlaylist {
+    pub id: i32,
+}
+
+impl Message for GetPlaylist {
+    type Result = Result<PlaylistWithSongs, PlaylistError>;
+}
+
+impl Handler<GetPlaylist> for DBExecutor {
+    type Result = Result<PlaylistWithSongs, PlaylistError>;
+
+    fn handle(&mut self, msg: GetPlaylist, _ctx: &mut Self::Context) -> Self::Result {
+        get_playlist(&self.get_conn(), msg.id)
+    }
+}
+
+/// Create playlist with given name and songs.
+pub struct CreatePlaylist {
+    pub playlist: PlaylistRequest,
+}
+
+impl Message for CreatePlaylist {
+    type Result = Result<PlaylistWithSongs, PlaylistError>;
+}
+
+impl Handler<CreatePlaylist> for DBExecutor {
+    type Result = Result<PlaylistWithSongs, PlaylistError>;
+
+    fn handle(&mut self, msg: CreatePlaylist, _ctx: &mut Self::Context) -> Self::Result {
+        create_playlist(&self.get_conn(), &msg.playlist)
+    }
+}
+
+/// Rename playlist with given id or change its songs.
+pub struct UpdatePlaylist {
+    pub id: i32,
+    pub update: PlaylistUpdate,
+}
+
+impl Message for UpdatePlaylist {
+    type Result = Result<PlaylistWithSongs, PlaylistError>;
+}
+
+impl Handler<UpdatePlaylist> for DBExecutor {
+    type Result = Result<PlaylistWithSongs, PlaylistError>;
+
+    fn handle(&mut self, msg: UpdatePlaylist, _ctx: &mut Self::Context) -> Self::Result {
+        update_playlist(&self.get_conn(), msg.id, &msg.update)
+    }
+}
+
+/// Delete playlist with given id, its songs stay in the database.
+pub struct DeletePlaylist {
+    pub id: i32,
+}
+
+impl Message for DeletePlaylist {
+    type Result = Result<(), PlaylistError>;
+}
+
+impl Handler<DeletePlaylist> for DBExecutor {
+    type Result = Result<(), PlaylistError>;
+
+    fn handle(&mut self, msg: DeletePlaylist, _ctx: &mut Self::Context) -> Self::Result {
+        delete_playlist(&self.get_conn(), msg.id)
+    }
+}
+
+/// Get song picked by the autoplay from songs with nsfw and broken set to false.
+pub struct GetAutoplaySong {
+    pub config: AutoplayConfig,
+}
+
+impl Message for GetAutoplaySong {
+    type Result = Result<Song, DieselError>;
+}
+
+impl Handler<GetAutoplaySong> for DBExecutor {
+    type Result = Result<Song, DieselError>;
+
+    fn handle(&mut self, msg: GetAutoplaySong, _ctx: &mut Self::Context) -> Self::Result {
+        get_autoplay_song(&self.get_conn(), &msg.config)
+    }
+}
+
+/// Save song that just went on air.
+pub struct StartPlayedSong {
+    pub song: NewPlayedSong,
+}
+
+impl Message for StartPlayedSong {
+    type Result = Result<(), DieselError>;
+}
+
+impl Handler<StartPlayedSong> for DBExecutor {
+    type Result = Result<(), DieselError>;
+
+    fn handle(&mut self, msg: StartPlayedSong, _ctx: &mut Self::Context) -> Self::Result {
+        start_played_song(&self.get_conn(), &msg.song)
+    }
+}
+
+/// Mark song that was on air as ended.
+pub struct FinishPlayedSong {
+    pub end: SongEnd,
+}
+
+impl Message for FinishPlayedSong {
+    type Result = Result<(), DieselError>;
+}
+
+impl Handler<FinishPlayedSong> for DBExecutor {
+    type Result = Result<(), DieselError>;
+
+    fn handle(&mut self, msg: FinishPlayedSong, _ctx: &mut Self::Context) -> Self::Result {
+        finish_played_song(&self.get_conn(), msg.end)
+    }
+}
+
+/// Get ids of the songs that went on air since given time.
+pub struct GetPlayedSongIds {
+    pub since: NaiveDateTime,
+}
+
+impl Message for GetPlayedSongIds {
+    type Result = Result<Vec<i32>, DieselError>;
+}
+
+impl Handler<GetPlayedSongIds> for DBExecutor {
+    type Result = Result<Vec<i32>, DieselError>;
+
+    fn handle(&mut self, msg: GetPlayedSongIds, _ctx: &mut Self::Context) -> Self::Result {
+        play_history::table
+            .filter(play_history::started_at.ge(msg.since))
+            .select(play_history::song_id)
+            .distinct()
+            .load::<i32>(&self.get_conn())
+    }
+}
+
+/// Get page of songs that were on air along with the number of all of them.
+pub struct GetHistory {
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Message for GetHistory {
+    type Result = Result<(Vec<HistoryEntry>, i64), DieselError>;
+}
+
+impl Handler<GetHistory> for DBExecutor {
+    type Result = Result<(Vec<HistoryEntry>, i64), DieselError>;
+
+    fn handle(&mut self, msg: GetHistory, _ctx: &mut Self::Context) -> Self::Result {
+        get_history(&self.get_conn(), msg.offset, msg.limit)
+    }
+}
+
+/// Get all of the timed entries, the ones played first go first.
+pub struct GetTimedEntries;
+
+impl Message for GetTimedEntries {
+    type Result = Result<Vec<TimedEntry>, TimedEntryError>;
+}
+
+impl Handler<GetTimedEntries> for DBExecutor {
+    type Result = Result<Vec<TimedEntry>, TimedEntryError>;
+
+    fn handle(&mut self, _msg: GetTimedEntries, _ctx: &mut Self::Context) -> Self::Result {
+        get_timed_entries(&self.get_conn())
+    }
+}
+
+/// Save timed entry whose song or playlist exists.
+pub struct SaveTimedEntry {
+    pub entry: NewTimedEntry,
+}
+
+impl Message for SaveTimedEntry {
+    type Result = Result<TimedEntry, TimedEntryError>;
+}
+
+impl Handler<SaveTimedEntry> for DBExecutor {
+    type Result = Result<TimedEntry, TimedEntryError>;
+
+    fn handle(&mut self, msg: SaveTimedEntry, _ctx: &mut Self::Context) -> Self::Result {
+        save_timed_entry(&self.get_conn(), &msg.entry)
+    }
+}
+
+/// Delete timed entry with given id.
+pub struct DeleteTimedEntry {
+    pub id: i32,
+}
+
+impl Message for DeleteTimedEntry {
+    type Result = Result<(), TimedEntryError>;
+}
+
+impl Handler<DeleteTimedEntry> for DBExecutor {
+    type Result = Result<(), TimedEntryError>;
+
+    fn handle(&mut self, msg: DeleteTimedEntry, _ctx: &mut Self::Context) -> Self::Result {
+        delete_timed_entry(&self.get_conn(), msg.id)
+    }
 }
 
-/// Saves song in database.
+/// Set when the recurring timed entry is played next time.
+pub struct SetTimedEntryTime {
+    pub id: i32,
+    pub at: NaiveDateTime,
+}
+
+impl Message for SetTimedEntryTime {
+    type Result = Result<(), DieselError>;
+}
+
+impl Handler<SetTimedEntryTime> for DBExecutor {
+    type Result = Result<(), DieselError>;
+
+    fn handle(&mut self, msg: SetTimedEntryTime, _ctx: &mut Self::Context) -> Self::Result {
+        diesel::update(timed_entries::table.find(msg.id))
+            .set(timed_entries::at.eq(msg.at))
+            .execute(&self.get_conn())
+            .map(|_| ())
+    }
+}
+
+/// Get songs played by the timed entry, in the order they're played in.
+pub struct GetTimedSongs {
+    pub entry: TimedEntry,
+}
+
+impl Message for GetTimedSongs {
+    type Result = Result<Vec<Song>, DieselError>;
+}
+
+impl Handler<GetTimedSongs> for DBExecutor {
+    type Result = Result<Vec<Song>, DieselError>;
+
+    fn handle(&mut self, msg: GetTimedSongs, _ctx: &mut Self::Context) -> Self::Result {
+        get_timed_songs(&self.get_conn(), &msg.entry)
+    }
+}
+
+/// Returns song picked by the autoplay from songs with nsfw and broken set to false.
+/// Only songs of the autoplay's playlist are picked if it's set.
+fn get_autoplay_song(conn: &PooledConn, config: &AutoplayConfig) -> Result<Song, DieselError> {
+    use super::schema::songs::dsl::{broken, nsfw};
+    let playlist_songs = match config.playlist {
+        Some(playlist_id) => playlist_songs::table
+            .inner_join(songs::table)
+            .filter(playlist_songs::playlist_id.eq(playlist_id))
+            .filter(nsfw.eq(false).and(broken.eq(false)))
+            .select(songs::all_columns)
+            .distinct()
+            .load::<Song>(conn)?,
+        None => Vec::new(),
+    };
+    // playlist that was deleted or has no songs that can be played doesn't stop the autoplay
+    let songs = if playlist_songs.is_empty() {
+        songs::table
+            .filter(nsfw.eq(false).and(broken.eq(false)))
+            .load::<Song>(conn)?
+    } else {
+        playlist_songs
+    };
+    let now = Utc::now().naive_utc();
+    let since = now - chrono::Duration::hours(i64::from(config.no_repeat_hours));
+    let history = get_recent_history(conn, config.no_repeat_songs, since)?;
+    let stats = get_play_stats(conn)?;
+    Autoplay::new(config, &history, &stats, now)
+        .pick(songs, &mut rand::thread_rng())
+        .ok_or(DieselError::NotFound)
+}
+
+/// Saves song in database and returns it.
+/// Songs can share the name and artists, so it's read back by the id it was given.
 fn save_song(conn: &PooledConn, song: &NewSong) -> Result<Song, DieselError> {
-    diesel::insert_into(songs::table).values(song).execute(conn);
-    get_song(conn, song.name.clone(), song.artists.clone())
+    use diesel::sql_types::Integer;
+
+    no_arg_sql_function!(
+        last_insert_rowid,
+        Integer,
+        "Represents the sql last_insert_rowid() function"
+    );
+    conn.transaction(|| {
+        diesel::insert_into(songs::table)
+            .values(song)
+            .execute(conn)?;
+        let song_id = diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+        songs::table.find(song_id).first::<Song>(conn)
+    })
 }
 
 /// Returns song with given id from database.
@@ -165,6 +586,11 @@ fn get_song(
         .first::<Song>(conn)
 }
 
+fn get_song_by_source_url(conn: &PooledConn, url: String) -> Result<Song, DieselError> {
+    use super::schema::songs::dsl::source_url;
+    songs::table.filter(source_url.eq(url)).first::<Song>(conn)
+}
+
 /// Returns all available songs from database.
 fn get_all_songs(conn: &PooledConn) -> Result<Vec<Song>, DieselError> {
     songs::table.load::<Song>(conn)
@@ -189,7 +615,345 @@ fn delete_song(conn: &PooledConn, song_id: i32) -> Result<Song, DieselError> {
         .filter(id.eq(song_id))
         .limit(1)
         .first::<Song>(conn)?;
+    diesel::delete(queued_songs::table.filter(queued_songs::song_id.eq(song_id))).execute(conn)?;
+    // history is kept, so songs played before still count e.g for the autoplay
+    diesel::delete(playlist_songs::table.filter(playlist_songs::song_id.eq(song_id)))
+        .execute(conn)?;
+    diesel::delete(timed_entries::table.filter(timed_entries::song_id.eq(song_id)))
+        .execute(conn)?;
     diesel::delete(songs::table.filter(id.eq(song_id))).execute(conn);
     std::fs::remove_file(&song.path);
     Ok(song)
 }
+
+/// Returns songs whose loudness wasn't measured yet.
+fn get_songs_without_gain(conn: &PooledConn) -> Result<Vec<Song>, DieselError> {
+    use super::schema::songs::dsl::gain;
+    songs::table.filter(gain.is_null()).load::<Song>(conn)
+}
+
+/// Sets song's gain.
+fn set_song_gain(conn: &PooledConn, song_id: i32, song_gain: f32) -> Result<Song, DieselError> {
+    use super::schema::songs::dsl::{gain, id};
+    diesel::update(songs::table.filter(id.eq(song_id)))
+        .set(gain.eq(song_gain))
+        .execute(conn)?;
+    songs::table
+        .filter(id.eq(song_id))
+        .limit(1)
+        .first::<Song>(conn)
+}
+
+/// Sets song's broken flag.
+fn mark_song_broken(conn: &PooledConn, song_id: i32) -> Result<Song, DieselError> {
+    use super::schema::songs::dsl::{broken, id};
+    diesel::update(songs::table.filter(id.eq(song_id)))
+        .set(broken.eq(true))
+        .execute(conn)?;
+    songs::table
+        .filter(id.eq(song_id))
+        .limit(1)
+        .first::<Song>(conn)
+}
+
+/// Returns queued songs along with the songs they refer to.
+fn get_queued_songs(conn: &PooledConn) -> Result<Vec<(QueuedSong, Song)>, DieselError> {
+    queued_songs::table
+        .inner_join(songs::table)
+        .order(queued_songs::requested_at)
+        .load::<(QueuedSong, Song)>(conn)
+}
+
+/// Saves song added to the queue.
+fn save_queued_song(conn: &PooledConn, song: &QueuedSong) -> Result<(), DieselError> {
+    diesel::insert_into(queued_songs::table)
+        .values(song)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Deletes song from the queue.
+fn delete_queued_song(conn: &PooledConn, song_uuid: &str) -> Result<(), DieselError> {
+    use super::schema::queued_songs::dsl::uuid;
+    diesel::delete(queued_songs::table.filter(uuid.eq(song_uuid))).execute(conn)?;
+    Ok(())
+}
+
+/// Drops the active song and marks song with given uuid as the active one.
+fn set_active_queued_song(conn: &PooledConn, song_uuid: Option<String>) -> Result<(), DieselError> {
+    use super::schema::queued_songs::dsl::{active, uuid};
+    let song_uuid = song_uuid.unwrap_or_default();
+    conn.transaction(|| {
+        // song that is played again after restart is already the active one
+        diesel::delete(queued_songs::table.filter(active.eq(true).and(uuid.ne(&song_uuid))))
+            .execute(conn)?;
+        diesel::update(queued_songs::table.filter(uuid.eq(&song_uuid)))
+            .set(active.eq(true))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Saves song that just went on air.
+fn start_played_song(conn: &PooledConn, song: &NewPlayedSong) -> Result<(), DieselError> {
+    diesel::insert_into(play_history::table)
+        .values(song)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Marks song that is on air as ended, there is at most one such song.
+fn finish_played_song(conn: &PooledConn, end: SongEnd) -> Result<(), DieselError> {
+    use super::schema::play_history::dsl::{ended_at, interrupted, skipped};
+    diesel::update(play_history::table.filter(ended_at.is_null()))
+        .set((
+            ended_at.eq(Utc::now().naive_utc()),
+            skipped.eq(end == SongEnd::Skipped),
+            interrupted.eq(end == SongEnd::Interrupted),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Returns songs that were on air, the most recent ones go first.
+fn get_history(
+    conn: &PooledConn,
+    offset: i64,
+    limit: i64,
+) -> Result<(Vec<HistoryEntry>, i64), DieselError> {
+    use super::schema::play_history::dsl::{id, started_at};
+    let entries = play_history::table
+        .inner_join(songs::table)
+        .order((started_at.desc(), id.desc()))
+        .offset(offset)
+        .limit(limit)
+        .load::<(PlayedSong, Song)>(conn)?
+        .into_iter()
+        .map(HistoryEntry::from)
+        .collect();
+    // entries of the deleted songs are kept, but they can't be returned without their songs
+    let total = play_history::table
+        .inner_join(songs::table)
+        .count()
+        .get_result(conn)?;
+    Ok((entries, total))
+}
+
+/// Returns the last `songs` songs that were on air and all of the songs that were on air since given time,
+/// the most recent ones go first. The song on air last is always returned.
+fn get_recent_history(
+    conn: &PooledConn,
+    songs: usize,
+    since: NaiveDateTime,
+) -> Result<Vec<HistoryEntry>, DieselError> {
+    use super::schema::play_history::dsl::{id, started_at};
+    let (last_songs, _total) = get_history(conn, 0, songs.max(1) as i64)?;
+    // both are the newest entries, so the longer one covers the other
+    let played_since = play_history::table
+        .inner_join(songs::table)
+        .filter(started_at.gt(since))
+        .order((started_at.desc(), id.desc()))
+        .load::<(PlayedSong, Song)>(conn)?;
+    if played_since.len() > last_songs.len() {
+        Ok(played_since.into_iter().map(HistoryEntry::from).collect())
+    } else {
+        Ok(last_songs)
+    }
+}
+
+/// Returns how many times every song was on air and how many votes it got in total.
+fn get_play_stats(conn: &PooledConn) -> Result<HashMap<i32, Stats>, DieselError> {
+    use super::schema::play_history::dsl::song_id;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{BigInt, Integer, Nullable};
+    // diesel can't select columns along with aggregates
+    let rows = play_history::table
+        .group_by(song_id)
+        .select(sql::<(Integer, BigInt, Nullable<BigInt>)>(
+            "song_id, COUNT(*), SUM(score)",
+        ))
+        .load::<(i32, i64, Option<i64>)>(conn)?;
+    Ok(rows
+        .into_iter()
+        .map(|(played_song_id, plays, likes)| {
+            let stats = Stats {
+                plays: plays as u32,
+                likes: likes.unwrap_or(0) as i32,
+            };
+            (played_song_id, stats)
+        })
+        .collect())
+}
+
+/// Returns songs of the playlist in the order they're played in.
+fn get_playlist_songs(conn: &PooledConn, playlist_id: i32) -> Result<Vec<Song>, DieselError> {
+    playlist_songs::table
+        .inner_join(songs::table)
+        .filter(playlist_songs::playlist_id.eq(playlist_id))
+        .order(playlist_songs::position)
+        .select(songs::all_columns)
+        .load::<Song>(conn)
+}
+
+/// Returns playlist with given id along with its songs.
+fn get_playlist(conn: &PooledConn, playlist_id: i32) -> Result<PlaylistWithSongs, PlaylistError> {
+    let playlist = playlists::table.find(playlist_id).first::<Playlist>(conn)?;
+    let songs = get_playlist_songs(conn, playlist_id)?;
+    Ok(PlaylistWithSongs { playlist, songs })
+}
+
+/// Returns all of the playlists along with their songs.
+fn get_playlists(conn: &PooledConn) -> Result<Vec<PlaylistWithSongs>, PlaylistError> {
+    playlists::table
+        .order(playlists::name)
+        .load::<Playlist>(conn)?
+        .into_iter()
+        .map(|playlist| {
+            let songs = get_playlist_songs(conn, playlist.id)?;
+            Ok(PlaylistWithSongs { playlist, songs })
+        })
+        .collect()
+}
+
+/// Replaces songs of the playlist with given songs, they're played in the given order.
+fn set_playlist_songs(
+    conn: &PooledConn,
+    playlist_id: i32,
+    song_ids: &[i32],
+) -> Result<(), PlaylistError> {
+    let existing_ids = songs::table
+        .select(songs::id)
+        .filter(songs::id.eq_any(song_ids))
+        .load::<i32>(conn)?;
+    if let Some(song_id) = song_ids.iter().find(|id| !existing_ids.contains(id)) {
+        return Err(PlaylistError::UnknownSong(*song_id));
+    }
+    diesel::delete(playlist_songs::table.filter(playlist_songs::playlist_id.eq(playlist_id)))
+        .execute(conn)?;
+    for (position, song_id) in song_ids.iter().enumerate() {
+        diesel::insert_into(playlist_songs::table)
+            .values(&NewPlaylistSong {
+                playlist_id,
+                song_id: *song_id,
+                position: position as i32,
+            })
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Saves new playlist along with its songs.
+fn create_playlist(
+    conn: &PooledConn,
+    request: &PlaylistRequest,
+) -> Result<PlaylistWithSongs, PlaylistError> {
+    validate_name(&request.name)?;
+    conn.transaction(|| {
+        diesel::insert_into(playlists::table)
+            .values(&NewPlaylist {
+                name: request.name.clone(),
+            })
+            .execute(conn)?;
+        let playlist_id = playlists::table
+            .select(playlists::id)
+            .order(playlists::id.desc())
+            .first::<i32>(conn)?;
+        set_playlist_songs(conn, playlist_id, &request.songs)?;
+        get_playlist(conn, playlist_id)
+    })
+}
+
+/// Renames the playlist or replaces its songs.
+fn update_playlist(
+    conn: &PooledConn,
+    playlist_id: i32,
+    update: &PlaylistUpdate,
+) -> Result<PlaylistWithSongs, PlaylistError> {
+    conn.transaction(|| {
+        playlists::table.find(playlist_id).first::<Playlist>(conn)?;
+        if let Some(name) = update.name.as_ref() {
+            validate_name(name)?;
+            diesel::update(playlists::table.find(playlist_id))
+                .set(playlists::name.eq(name))
+                .execute(conn)?;
+        }
+        if let Some(song_ids) = update.songs.as_ref() {
+            set_playlist_songs(conn, playlist_id, song_ids)?;
+        }
+        get_playlist(conn, playlist_id)
+    })
+}
+
+/// Deletes the playlist, its songs stay in the database.
+fn delete_playlist(conn: &PooledConn, playlist_id: i32) -> Result<(), PlaylistError> {
+    conn.transaction(|| {
+        diesel::delete(playlist_songs::table.filter(playlist_songs::playlist_id.eq(playlist_id)))
+            .execute(conn)?;
+        diesel::delete(timed_entries::table.filter(timed_entries::playlist_id.eq(playlist_id)))
+            .execute(conn)?;
+        let deleted = diesel::delete(playlists::table.find(playlist_id)).execute(conn)?;
+        if deleted == 0 {
+            return Err(PlaylistError::NotFound);
+        }
+        Ok(())
+    })
+}
+
+/// Returns all of the timed entries, the ones played first go first.
+fn get_timed_entries(conn: &PooledConn) -> Result<Vec<TimedEntry>, TimedEntryError> {
+    let entries = timed_entries::table
+        .order(timed_entries::at)
+        .load::<TimedEntryRow>(conn)?
+        .into_iter()
+        .map(TimedEntry::from)
+        .collect();
+    Ok(entries)
+}
+
+/// Saves timed entry if its song or playlist exists.
+fn save_timed_entry(
+    conn: &PooledConn,
+    entry: &NewTimedEntry,
+) -> Result<TimedEntry, TimedEntryError> {
+    if let Some(song_id) = entry.song_id {
+        songs::table
+            .find(song_id)
+            .first::<Song>(conn)
+            .optional()?
+            .ok_or(TimedEntryError::UnknownSong(song_id))?;
+    }
+    if let Some(playlist_id) = entry.playlist_id {
+        playlists::table
+            .find(playlist_id)
+            .first::<Playlist>(conn)
+            .optional()?
+            .ok_or(TimedEntryError::UnknownPlaylist(playlist_id))?;
+    }
+    conn.transaction(|| {
+        diesel::insert_into(timed_entries::table)
+            .values(entry)
+            .execute(conn)?;
+        let entry = timed_entries::table
+            .order(timed_entries::id.desc())
+            .first::<TimedEntryRow>(conn)?;
+        Ok(TimedEntry::from(entry))
+    })
+}
+
+/// Deletes timed entry with given id.
+fn delete_timed_entry(conn: &PooledConn, entry_id: i32) -> Result<(), TimedEntryError> {
+    let deleted = diesel::delete(timed_entries::table.find(entry_id)).execute(conn)?;
+    if deleted == 0 {
+        return Err(TimedEntryError::NotFound);
+    }
+    Ok(())
+}
+
+/// Returns song of the timed entry or songs of its playlist.
+fn get_timed_songs(conn: &PooledConn, entry: &TimedEntry) -> Result<Vec<Song>, DieselError> {
+    match (entry.song_id, entry.playlist_id) {
+        (_, Some(playlist_id)) => get_playlist_songs(conn, playlist_id),
+        (Some(song_id), None) => songs::table.find(song_id).load::<Song>(conn),
+        (None, None) => Ok(Vec::new()),
+    }
+}
@@ -0,0 +1,498 @@
+use crate::client_publisher::ClientPublisher;
+use crate::config::Config;
+use crate::responses::{get_standard_failure_response, get_standard_success_response};
+use crate::song::SongRequest;
+use crate::system::AppState;
+use crate::web_socket::UserMessage;
+use actix::prelude::*;
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Path, State};
+use failure::Fail;
+use futures::future::Future;
+use futures::sync::oneshot;
+use serde::{Deserialize, Serialize};
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    /// Waiting for its turn or a free IO worker.
+    Queued,
+    /// youtube-dl is downloading the video.
+    Downloading,
+    /// Audio is being extracted from the downloaded video.
+    Converting,
+    /// Loudness of the downloaded song is being measured, it can't be cancelled anymore.
+    Measuring,
+}
+
+#[derive(Fail, Debug)]
+/// Why the song couldn't be downloaded.
+pub enum DownloadError {
+    #[fail(display = "no video was found")]
+    NotFound,
+    #[fail(display = "network error: {}", _0)]
+    Network(String),
+    #[fail(display = "video can't be downloaded: {}", _0)]
+    Unsupported(String),
+    #[fail(display = "download timed out")]
+    Timeout,
+    #[fail(display = "couldn't read song's info: {}", _0)]
+    Parse(String),
+    #[fail(display = "download was cancelled")]
+    Cancelled,
+    #[fail(display = "youtube-dl failed: {}", _0)]
+    Failed(String),
+}
+
+impl DownloadError {
+    /// Tells what went wrong from the errors printed by youtube-dl.
+    pub fn from_output(output: &str) -> Self {
+        let message = output
+            .lines()
+            .rev()
+            .find(|line| line.starts_with("ERROR:"))
+            .unwrap_or_else(|| output.trim())
+            .trim_start_matches("ERROR:")
+            .trim()
+            .to_owned();
+        let lowercase = message.to_lowercase();
+        if lowercase.contains("timed out") {
+            DownloadError::Timeout
+        } else if lowercase.contains("unsupported url")
+            || lowercase.contains("unavailable")
+            || lowercase.contains("copyright")
+            || lowercase.contains("sign in")
+        {
+            DownloadError::Unsupported(message)
+        } else if lowercase.contains("urlopen error")
+            || lowercase.contains("unable to download")
+            || lowercase.contains("http error")
+            || lowercase.contains("connection")
+        {
+            DownloadError::Network(message)
+        } else {
+            DownloadError::Failed(message)
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            DownloadError::NotFound => "not_found",
+            DownloadError::Network(_) => "network",
+            DownloadError::Unsupported(_) => "unsupported",
+            DownloadError::Timeout => "timeout",
+            DownloadError::Parse(_) => "parse",
+            DownloadError::Cancelled => "cancelled",
+            DownloadError::Failed(_) => "failed",
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct DownloadFailed {
+    pub name: String,
+    pub artists: String,
+    pub url: Option<String>,
+    pub reason: &'static str,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+/// Song that is being downloaded.
+pub struct DownloadJob {
+    pub id: Uuid,
+    pub name: String,
+    pub artists: String,
+    pub url: Option<String>,
+    pub status: DownloadStatus,
+    // percent of the video that was downloaded
+    pub progress: f32,
+    #[serde(skip_serializing)]
+    requested_by: Option<String>,
+    // turn the song is going to be played in, downloads of the earlier turns go first
+    // None if songs are played in the order they were requested in
+    #[serde(skip_serializing)]
+    turn: Option<usize>,
+}
+
+impl DownloadJob {
+    /// With round robin order songs are played in turns, `queued` is how many songs the client already has in the queue then.
+    pub fn new(requested_song: &SongRequest, queued: Option<usize>) -> Self {
+        DownloadJob {
+            id: Uuid::new_v4(),
+            name: requested_song.name.clone(),
+            artists: requested_song.artists.clone(),
+            url: requested_song.url.clone(),
+            status: DownloadStatus::Queued,
+            progress: 0.0,
+            requested_by: requested_song.requested_by.clone(),
+            turn: queued,
+        }
+    }
+}
+
+/// Reads progress from the line printed by youtube-dl with --newline,
+/// e.g "[download]  45.3% of 3.52MiB at 1.23MiB/s ETA 00:02".
+pub fn parse_progress(line: &str) -> Option<(DownloadStatus, f32)> {
+    if line.starts_with("[ffmpeg]") {
+        return Some((DownloadStatus::Converting, 100.0));
+    }
+    if !line.starts_with("[download]") {
+        return None;
+    }
+    line.split_whitespace()
+        .find(|word| word.ends_with('%'))
+        .and_then(|percent| percent.trim_end_matches('%').parse().ok())
+        .map(|progress| (DownloadStatus::Downloading, progress))
+}
+
+/// Keeps track of the downloads, so clients can see and cancel them.
+/// IO workers tell it how their downloads go, because they can't be asked while they're downloading.
+/// Only a few songs are downloaded at once, the rest wait for their turn here instead of the IO workers' mailbox,
+/// so songs that are going to be played sooner can be downloaded first.
+pub struct Downloads {
+    jobs: Vec<DownloadJob>,
+    // jobs waiting for their turn in the order they were requested in, they're started by sending to the channel
+    waiting: Vec<(Uuid, oneshot::Sender<()>)>,
+    // jobs that were handed to the IO workers
+    running: Vec<Uuid>,
+    // youtube-dl processes of the jobs that are downloading, they're killed when jobs are cancelled
+    // and their flags are set, so the IO workers can tell it from other reasons youtube-dl was killed for
+    processes: Vec<(Uuid, Arc<Mutex<Child>>, Arc<AtomicBool>)>,
+    // how many songs can be downloaded at once
+    max_downloads: usize,
+}
+
+impl Default for Downloads {
+    fn default() -> Self {
+        Downloads {
+            jobs: Vec::new(),
+            waiting: Vec::new(),
+            running: Vec::new(),
+            processes: Vec::new(),
+            max_downloads: Config::default().max_downloads,
+        }
+    }
+}
+
+impl Actor for Downloads {
+    type Context = Context<Self>;
+}
+
+impl actix::Supervised for Downloads {}
+
+impl SystemService for Downloads {}
+
+impl Downloads {
+    /// Lets all of the clients know how the download goes.
+    fn broadcast_job(&self, id: Uuid) {
+        if let Some(job) = self.jobs.iter().find(|job| job.id == id) {
+            let response = UserMessage::<DownloadJob> {
+                success: true,
+                action: "download_progress".to_owned(),
+                value: job.clone(),
+            };
+            ClientPublisher::from_registry().do_send(response);
+        }
+    }
+
+    fn get_turn(&self, id: Uuid) -> usize {
+        self.jobs
+            .iter()
+            .find(|job| job.id == id)
+            .and_then(|job| job.turn)
+            .unwrap_or(0)
+    }
+
+    /// Starts waiting jobs while fewer than max_downloads songs are downloaded, jobs of the earliest turn go first.
+    fn start_downloads(&mut self) {
+        while self.running.len() < self.max_downloads {
+            // the first one is picked out of the jobs with the same turn, so they start in the order they were requested in
+            let next = self
+                .waiting
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (id, _))| self.get_turn(*id))
+                .map(|(index, _)| index);
+            let index = match next {
+                Some(index) => index,
+                None => return,
+            };
+            let (id, start) = self.waiting.remove(index);
+            // nobody waits for the job anymore if the queue was restarted
+            if start.send(()).is_ok() {
+                self.running.push(id);
+            } else {
+                self.jobs.retain(|job| job.id != id);
+            }
+        }
+    }
+}
+
+/// Track download that waits for its turn, the job can be handed to an IO worker once `start` is sent to.
+/// `start` is dropped if the download is cancelled before that.
+pub struct AddDownload {
+    pub job: DownloadJob,
+    pub start: oneshot::Sender<()>,
+}
+
+impl Message for AddDownload {
+    type Result = ();
+}
+
+impl Handler<AddDownload> for Downloads {
+    type Result = ();
+    fn handle(&mut self, msg: AddDownload, _ctx: &mut Self::Context) -> Self::Result {
+        let mut job = msg.job;
+        // songs of the client that are still being downloaded are going to be played in the earlier turns
+        job.turn = job.turn.map(|queued| {
+            queued
+                + self
+                    .jobs
+                    .iter()
+                    .filter(|other| other.requested_by == job.requested_by)
+                    .count()
+        });
+        let id = job.id;
+        self.jobs.push(job);
+        self.waiting.push((id, msg.start));
+        self.broadcast_job(id);
+        self.start_downloads();
+    }
+}
+
+/// Sent by the IO worker once youtube-dl is started, returns false if the job was cancelled in the meantime.
+/// `cancelled` is set if the job is cancelled later.
+pub struct StartDownload {
+    pub id: Uuid,
+    pub process: Arc<Mutex<Child>>,
+    pub cancelled: Arc<AtomicBool>,
+}
+
+impl Message for StartDownload {
+    type Result = bool;
+}
+
+impl Handler<StartDownload> for Downloads {
+    type Result = bool;
+    fn handle(&mut self, msg: StartDownload, _ctx: &mut Self::Context) -> Self::Result {
+        let job = match self.jobs.iter_mut().find(|job| job.id == msg.id) {
+            Some(job) => job,
+            None => return false,
+        };
+        job.status = DownloadStatus::Downloading;
+        self.processes.push((msg.id, msg.process, msg.cancelled));
+        self.broadcast_job(msg.id);
+        true
+    }
+}
+
+pub struct SetDownloadProgress {
+    pub id: Uuid,
+    pub status: DownloadStatus,
+    pub progress: f32,
+}
+
+impl Message for SetDownloadProgress {
+    type Result = ();
+}
+
+impl Handler<SetDownloadProgress> for Downloads {
+    type Result = ();
+    fn handle(&mut self, msg: SetDownloadProgress, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == msg.id) {
+            job.status = msg.status;
+            job.progress = msg.progress;
+            self.broadcast_job(msg.id);
+        }
+    }
+}
+
+/// Stop tracking download that finished or failed, next waiting job is started in its place.
+pub struct FinishDownload {
+    pub id: Uuid,
+}
+
+impl Message for FinishDownload {
+    type Result = ();
+}
+
+impl Handler<FinishDownload> for Downloads {
+    type Result = ();
+    fn handle(&mut self, msg: FinishDownload, _ctx: &mut Self::Context) -> Self::Result {
+        self.jobs.retain(|job| job.id != msg.id);
+        self.processes.retain(|(id, _, _)| *id != msg.id);
+        self.running.retain(|id| *id != msg.id);
+        self.start_downloads();
+    }
+}
+
+/// Change how many songs can be downloaded at once.
+pub struct SetDownloadLimit {
+    pub max_downloads: usize,
+}
+
+impl Message for SetDownloadLimit {
+    type Result = ();
+}
+
+impl Handler<SetDownloadLimit> for Downloads {
+    type Result = ();
+    fn handle(&mut self, msg: SetDownloadLimit, _ctx: &mut Self::Context) -> Self::Result {
+        // downloads over the lower limit aren't stopped, new ones just wait until they finish
+        self.max_downloads = msg.max_downloads;
+        self.start_downloads();
+    }
+}
+
+pub struct GetDownloads;
+
+impl Message for GetDownloads {
+    type Result = Vec<DownloadJob>;
+}
+
+impl Handler<GetDownloads> for Downloads {
+    type Result = MessageResult<GetDownloads>;
+    fn handle(&mut self, _msg: GetDownloads, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.jobs.clone())
+    }
+}
+
+/// Cancel download with given id, returns false if there is no such download or it's already downloaded.
+/// youtube-dl is killed and the IO worker cleans up files it left behind.
+pub struct CancelDownload {
+    pub id: Uuid,
+}
+
+impl Message for CancelDownload {
+    type Result = bool;
+}
+
+/// Lets all of the clients know that the download was cancelled.
+impl Handler<CancelDownload> for Downloads {
+    type Result = bool;
+    fn handle(&mut self, msg: CancelDownload, _ctx: &mut Self::Context) -> Self::Result {
+        let index = match self.jobs.iter().position(|job| job.id == msg.id) {
+            Some(index) => index,
+            None => return false,
+        };
+        if self.jobs[index].status == DownloadStatus::Measuring {
+            return false;
+        }
+        self.jobs.remove(index);
+        // job that is still waiting is never started, its `start` is dropped
+        self.waiting.retain(|(id, _)| *id != msg.id);
+        if let Some(index) = self.processes.iter().position(|(id, _, _)| *id == msg.id) {
+            let (_, process, cancelled) = self.processes.remove(index);
+            cancelled.store(true, Ordering::SeqCst);
+            let killed = process.lock().unwrap().kill();
+            if let Err(e) = killed {
+                println!("couldn't kill youtube-dl - {}", e);
+            }
+        }
+        let response = UserMessage::<Uuid> {
+            success: true,
+            action: "download_cancelled".to_owned(),
+            value: msg.id,
+        };
+        ClientPublisher::from_registry().do_send(response);
+        true
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DownloadId {
+    id: Uuid,
+}
+
+// API functions
+/// GET /downloads
+/// Songs that are being downloaded or wait for it, in the order they were requested in.
+pub fn get_downloads(_state: State<AppState>) -> FutureResponse<HttpResponse> {
+    Downloads::from_registry()
+        .send(GetDownloads {})
+        .and_then(|jobs| Ok(HttpResponse::Ok().json(jobs)))
+        .from_err()
+        .responder()
+}
+
+/// DELETE /downloads/{id}
+pub fn cancel_download(path: Path<DownloadId>) -> FutureResponse<HttpResponse> {
+    Downloads::from_registry()
+        .send(CancelDownload { id: path.id })
+        .and_then(|cancelled| {
+            if cancelled {
+                Ok(HttpResponse::Ok().json(get_standard_success_response()))
+            } else {
+                Ok(HttpResponse::NotFound().json(get_standard_failure_response()))
+            }
+        })
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_errors_are_network_errors() {
+        let output = "WARNING: Retrying (1/3)...\nERROR: Unable to download webpage: <urlopen error [Errno -2] Name or service not known> (caused by URLError(gaierror(-2, 'Name or service not known')))\n";
+        match DownloadError::from_output(output) {
+            DownloadError::Network(message) => {
+                assert!(message.starts_with("Unable to download webpage"))
+            }
+            error => panic!("expected network error, got {:?}", error),
+        }
+        let output = "ERROR: Unable to download webpage: HTTP Error 429: Too Many Requests";
+        assert_eq!(DownloadError::from_output(output).reason(), "network");
+    }
+
+    #[test]
+    fn videos_that_cant_be_downloaded_are_unsupported() {
+        for output in &[
+            "ERROR: Unsupported URL: https://example.com/song",
+            "ERROR: This video is unavailable.",
+            "ERROR: Sign in to confirm your age\nThis video may be inappropriate for some users.",
+            "ERROR: This video contains content from UMG, who has blocked it on copyright grounds.",
+        ] {
+            assert_eq!(DownloadError::from_output(output).reason(), "unsupported");
+        }
+    }
+
+    #[test]
+    fn timed_out_downloads_are_timeouts() {
+        let output = "ERROR: unable to download video data: <urlopen error timed out>";
+        assert_eq!(DownloadError::from_output(output).reason(), "timeout");
+    }
+
+    #[test]
+    fn last_error_is_taken() {
+        let output = "ERROR: This video is unavailable.\n[youtube] abc: Downloading webpage\nERROR: Incomplete YouTube ID abc. URL https://www.youtube.com/watch?v=abc looks truncated.\n";
+        match DownloadError::from_output(output) {
+            DownloadError::Failed(message) => assert_eq!(
+                message,
+                "Incomplete YouTube ID abc. URL https://www.youtube.com/watch?v=abc looks truncated."
+            ),
+            error => panic!("expected failed download, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn output_without_errors_is_taken_as_it_is() {
+        match DownloadError::from_output(
+            "  Traceback (most recent call last):\n  KeyError: 'formats'\n",
+        ) {
+            DownloadError::Failed(message) => {
+                assert_eq!(
+                    message,
+                    "Traceback (most recent call last):\n  KeyError: 'formats'"
+                )
+            }
+            error => panic!("expected failed download, got {:?}", error),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::db::GetHistory;
+use super::responses::get_standard_failure_response;
+use super::schema::play_history;
+use super::song::Song;
+use super::system::AppState;
+use actix_web::{
+    AsyncResponder, Error as AWError, FutureResponse, HttpResponse, Query, ResponseError, State,
+};
+use chrono::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::{Insertable, Queryable};
+use failure::Fail;
+use futures::future::Future;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// How many entries are returned by default.
+const DEFAULT_PAGE_SIZE: i64 = 20;
+/// Upper bound of entries returned at once.
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+/// Why the song went on air.
+pub enum PlaySource {
+    /// Song was requested by one of the clients.
+    Requested,
+    /// Queue was empty, so a random song was picked.
+    Autoplay,
+}
+
+impl PlaySource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlaySource::Requested => "requested",
+            PlaySource::Autoplay => "autoplay",
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Clone, Debug)]
+/// Song that was on air, dates are in UTC.
+pub struct PlayedSong {
+    pub id: i32,
+    pub song_id: i32,
+    pub started_at: NaiveDateTime,
+    // None while song is still on air
+    pub ended_at: Option<NaiveDateTime>,
+    pub skipped: bool,
+    pub source: String,
+    // sum of the votes song got while it was queued
+    pub score: i32,
+    // whether song was cut off by a timed entry
+    pub interrupted: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+/// Why the song went off air.
+pub enum SongEnd {
+    /// Song was played until its end.
+    Played,
+    /// Clients voted for skipping the song.
+    Skipped,
+    /// Timed entry cut off the song.
+    Interrupted,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "play_history"]
+pub struct NewPlayedSong {
+    pub song_id: i32,
+    pub started_at: NaiveDateTime,
+    pub source: String,
+    pub score: i32,
+}
+
+impl NewPlayedSong {
+    /// Song that just went on air.
+    pub fn new(song: &Song, source: PlaySource, score: i32) -> Self {
+        NewPlayedSong {
+            song_id: song.id,
+            started_at: Utc::now().naive_utc(),
+            source: source.as_str().to_owned(),
+            score,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub played_song: PlayedSong,
+    pub song: Song,
+}
+
+impl From<(PlayedSong, Song)> for HistoryEntry {
+    fn from((played_song, song): (PlayedSong, Song)) -> Self {
+        HistoryEntry { played_song, song }
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum HistoryError {
+    #[fail(display = "page is out of range")]
+    PageOutOfRange,
+    #[fail(display = "database error: {}", _0)]
+    Database(#[cause] DieselError),
+}
+
+impl From<DieselError> for HistoryError {
+    fn from(error: DieselError) -> Self {
+        HistoryError::Database(error)
+    }
+}
+
+impl ResponseError for HistoryError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            HistoryError::PageOutOfRange => HttpResponse::BadRequest(),
+            HistoryError::Database(_) => HttpResponse::InternalServerError(),
+        }
+        .json(get_standard_failure_response())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HistoryPage {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+// API functions
+/// GET /history?page=0&per_page=20
+/// Songs that were on air, the most recent ones go first.
+pub fn get_history(
+    query: Query<HistoryPage>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    let page = query.page.unwrap_or(0).max(0);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = match page.checked_mul(per_page) {
+        Some(offset) => offset,
+        None => {
+            return Box::new(futures::future::err(AWError::from(
+                HistoryError::PageOutOfRange,
+            )))
+        }
+    };
+    state
+        .db
+        .send(GetHistory {
+            offset,
+            limit: per_page,
+        })
+        .from_err()
+        .and_then(|res| res.map_err(|e| AWError::from(HistoryError::from(e))))
+        .map(move |(entries, total)| {
+            HttpResponse::Ok().json(json!({
+                "entries": entries,
+                "page": page,
+                "per_page": per_page,
+                "total": total,
+            }))
+        })
+        .responder()
+}
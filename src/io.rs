@@ -1,7 +1,11 @@
+use super::audio::measure_gain;
+use super::download::DownloadError;
+use super::search::{search_youtube, SearchResult};
 use super::song::{download_song, Song};
 use crate::db::DBExecutor;
 use crate::song::{NewSong, SongRequest};
 use actix::*;
+use uuid::Uuid;
 
 /// IO is an actor of SyncContext in order to be able to spawn it with SyncArbiter
 /// It allows for multiple IO operations at once, so e.g few downloads from youtube
@@ -12,11 +16,15 @@ pub struct MyIO {
 
 #[derive(Debug)]
 pub enum IOJob {
-    DownloadSong { requested_song: SongRequest },
+    DownloadSong {
+        requested_song: SongRequest,
+        // id of the job tracked by the Downloads
+        job_id: Uuid,
+    },
 }
 
 impl Message for IOJob {
-    type Result = Result<NewSong, ()>;
+    type Result = Result<NewSong, DownloadError>;
 }
 
 impl Actor for MyIO {
@@ -24,14 +32,54 @@ impl Actor for MyIO {
 }
 
 impl Handler<IOJob> for MyIO {
-    type Result = Result<NewSong, ()>;
+    type Result = Result<NewSong, DownloadError>;
 
     fn handle(&mut self, msg: IOJob, ctx: &mut Self::Context) -> Self::Result {
         match msg {
-            IOJob::DownloadSong { requested_song } => {
-                // Result containing NewSong with all informations of it we need or empty error for now
-                download_song(&requested_song)
+            IOJob::DownloadSong {
+                requested_song,
+                job_id,
+            } => {
+                // Result containing NewSong with all informations of it we need or reason why it couldn't be downloaded
+                download_song(&requested_song, job_id)
             }
         }
     }
 }
+
+/// Measure loudness of the song at given path, returns gain in dB bringing it to the target loudness.
+/// Separate from IOJob, because its result isn't a song.
+pub struct MeasureGain {
+    pub path: String,
+}
+
+impl Message for MeasureGain {
+    type Result = Result<f32, ()>;
+}
+
+impl Handler<MeasureGain> for MyIO {
+    type Result = Result<f32, ()>;
+
+    fn handle(&mut self, msg: MeasureGain, _ctx: &mut Self::Context) -> Self::Result {
+        measure_gain(&msg.path)
+    }
+}
+
+/// Search youtube for videos matching the query without downloading any of them.
+/// Separate from IOJob, because its result isn't a song.
+pub struct SearchSongs {
+    pub query: String,
+    pub limit: usize,
+}
+
+impl Message for SearchSongs {
+    type Result = Result<Vec<SearchResult>, DownloadError>;
+}
+
+impl Handler<SearchSongs> for MyIO {
+    type Result = Result<Vec<SearchResult>, DownloadError>;
+
+    fn handle(&mut self, msg: SearchSongs, _ctx: &mut Self::Context) -> Self::Result {
+        search_youtube(&msg.query, msg.limit)
+    }
+}
@@ -1,15 +1,25 @@
 #![cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
 #[macro_use]
 mod song;
+mod audio;
+mod autoplay;
 mod client_publisher;
 mod config;
 mod db;
+mod download;
+mod history;
 mod io;
+mod playlist;
 mod radio;
 mod responses;
+mod schedule;
 mod schema;
+mod search;
 mod song_queue;
+mod stream;
 mod system;
+mod timed;
+mod transmitter;
 mod web_socket;
 use system::System;
 extern crate num_cpus;
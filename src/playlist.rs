@@ -0,0 +1,178 @@
+use super::db::{CreatePlaylist, DeletePlaylist, GetPlaylist, GetPlaylists, UpdatePlaylist};
+use super::responses::{get_standard_failure_response, get_standard_success_response};
+use super::schema::{playlist_songs, playlists};
+use super::song::Song;
+use super::system::AppState;
+use actix_web::{
+    AsyncResponder, Error as AWError, FutureResponse, HttpResponse, Json, Path, ResponseError,
+    State,
+};
+use diesel::result::Error as DieselError;
+use diesel::{Insertable, Queryable};
+use failure::Fail;
+use futures::future::Future;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Queryable, Clone, Debug)]
+pub struct Playlist {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "playlists"]
+pub struct NewPlaylist {
+    pub name: String,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "playlist_songs"]
+pub struct NewPlaylistSong {
+    pub playlist_id: i32,
+    pub song_id: i32,
+    pub position: i32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+/// Playlist along with its songs in the order they're played in.
+pub struct PlaylistWithSongs {
+    #[serde(flatten)]
+    pub playlist: Playlist,
+    pub songs: Vec<Song>,
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistRequest {
+    pub name: String,
+    // ids of the songs in the order they're played in
+    #[serde(default)]
+    pub songs: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+/// Changes to the playlist, fields that are missing are left as they were.
+pub struct PlaylistUpdate {
+    pub name: Option<String>,
+    // ids of the songs in the new order, songs that are left out are removed from the playlist
+    pub songs: Option<Vec<i32>>,
+}
+
+#[derive(Fail, Debug)]
+pub enum PlaylistError {
+    #[fail(display = "playlist doesn't exist")]
+    NotFound,
+    #[fail(display = "song {} doesn't exist", _0)]
+    UnknownSong(i32),
+    #[fail(display = "playlist's name can't be empty")]
+    EmptyName,
+    #[fail(display = "database error: {}", _0)]
+    Database(#[cause] DieselError),
+}
+
+impl From<DieselError> for PlaylistError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DieselError::NotFound => PlaylistError::NotFound,
+            error => PlaylistError::Database(error),
+        }
+    }
+}
+
+impl ResponseError for PlaylistError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PlaylistError::NotFound => HttpResponse::NotFound(),
+            PlaylistError::UnknownSong(_) | PlaylistError::EmptyName => HttpResponse::BadRequest(),
+            PlaylistError::Database(_) => HttpResponse::InternalServerError(),
+        }
+        .json(get_standard_failure_response())
+    }
+}
+
+/// Checks whether playlist can be saved with given name.
+pub fn validate_name(name: &str) -> Result<(), PlaylistError> {
+    if name.trim().is_empty() {
+        return Err(PlaylistError::EmptyName);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct PlaylistId {
+    id: i32,
+}
+
+// API functions
+/// GET /playlists
+pub fn get_playlists(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetPlaylists {})
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(|playlists| HttpResponse::Ok().json(playlists))
+        .responder()
+}
+
+/// GET /playlists/{id}
+pub fn get_playlist(
+    path: Path<PlaylistId>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetPlaylist { id: path.id })
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(|playlist| HttpResponse::Ok().json(playlist))
+        .responder()
+}
+
+/// POST /playlists
+pub fn create_playlist(
+    playlist: Json<PlaylistRequest>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(CreatePlaylist {
+            playlist: playlist.into_inner(),
+        })
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(|playlist| HttpResponse::Ok().json(playlist))
+        .responder()
+}
+
+/// PUT /playlists/{id}
+/// Renames the playlist or changes its songs and their order.
+pub fn update_playlist(
+    path: Path<PlaylistId>,
+    update: Json<PlaylistUpdate>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(UpdatePlaylist {
+            id: path.id,
+            update: update.into_inner(),
+        })
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(|playlist| HttpResponse::Ok().json(playlist))
+        .responder()
+}
+
+/// DELETE /playlists/{id}
+pub fn delete_playlist(
+    path: Path<PlaylistId>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(DeletePlaylist { id: path.id })
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(|_| HttpResponse::Ok().json(get_standard_success_response()))
+        .responder()
+}
@@ -1,37 +1,426 @@
+use crate::audio;
+use crate::client_publisher::ClientPublisher;
+use crate::config::{Config, ConfigUpdate};
+use crate::db::{DBExecutor, MarkSongBroken};
+use crate::responses::get_standard_failure_response;
 use crate::song::Song;
-use crate::song_queue::SongQueue;
+use crate::song_queue::{BroadcastState, SongQueue};
+use crate::stream::{Stream, StreamEvent, StreamOptions, StreamSong};
+use crate::transmitter::{
+    describe_exit, is_normal_exit, NullTransmitter, Rds, Transmission, Transmitter,
+};
+use crate::web_socket::UserMessage;
 use actix::fut::wrap_future;
 use actix::SpawnHandle;
 use actix::*;
-use futures::Future;
-use std::path::Path;
-use std::process::Command;
+use actix_web::{HttpResponse, ResponseError};
+use failure::Fail;
+use serde::Serialize;
+use std::io;
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant};
 use tokio_process::CommandExt;
 
-#[derive(Default)]
-/// Struct responsible for playing songs via PiFmAdv.
+/// Struct responsible for playing songs via selected transmitter.
 pub struct Radio {
-    script_path: String,
+    transmitter: Box<dyn Transmitter>,
     // handle to process playing song
     command_handle: Option<SpawnHandle>,
-    frequency: f32,
-    // is song played
-    playing: bool,
+    // ffmpeg process feeding the transmitter when song doesn't start from the beginning or needs gain
+    decoder: Option<Child>,
+    // continuous stream used instead of the command when songs are crossfaded or gapless
+    stream: Option<Stream>,
+    config: Config,
+    // song on air, None if nothing is played
+    playback: Option<Playback>,
+    // song handed over to the stream, which starts once the one on air is over
+    // it's played next even if the stream is stopped in the meantime, since the queue already moved on to it
+    upcoming: Option<Playback>,
+    // handle to the next attempt of the failed transmission
+    retry_handle: Option<SpawnHandle>,
+    db: Addr<DBExecutor>,
+    // why radio output is offline, None if the transmitter is available
+    offline_reason: Option<String>,
+}
+
+/// How many times transmitter can fail on a song before the song is marked as broken.
+const MAX_FAILURES: u32 = 3;
+/// Delay before the first retry of the failed transmission, it doubles with every failure.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Longest delay between retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Song on air with everything needed to resume it.
+struct Playback {
+    song: Song,
+    queue_addr: Addr<SongQueue>,
+    // seconds of the song played before the current transmission started
+    offset: i32,
+    // when the current transmission started, None if song is paused
+    started_at: Option<Instant>,
+    // failed attempts to transmit the song in a row
+    failures: u32,
+}
+
+impl Playback {
+    /// Seconds of the song that are already played.
+    fn elapsed(&self) -> i32 {
+        let transmitted = self
+            .started_at
+            .map(|started_at| started_at.elapsed().as_secs() as i32)
+            .unwrap_or(0);
+        (self.offset + transmitted).min(self.song.duration)
+    }
+
+    fn position(&self) -> PlaybackPosition {
+        let elapsed = self.elapsed();
+        PlaybackPosition {
+            song_id: self.song.id,
+            elapsed,
+            remaining: self.song.duration - elapsed,
+            paused: self.started_at.is_none(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+/// How far into the song the radio is.
+pub struct PlaybackPosition {
+    pub song_id: i32,
+    pub elapsed: i32,
+    pub remaining: i32,
+    pub paused: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+/// Whether songs actually go on air.
+pub struct RadioStatus {
+    pub transmitter: &'static str,
+    pub online: bool,
+    // why radio output is offline, None if it's online
+    pub offline_reason: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+/// Sent to the clients when transmission of the song fails.
+pub struct TransmissionFailure {
+    pub song_id: i32,
+    pub error: String,
+    // seconds until the next attempt, None if radio gave up on the song and marked it as broken
+    pub retry_in: Option<u64>,
 }
 
 impl Radio {
-    pub fn new() -> Self {
-        // panic if script isn't avialable
-        let script_path = get_script_path().unwrap();
+    pub fn new(transmitter: Box<dyn Transmitter>, db: Addr<DBExecutor>) -> Self {
+        println!("Using {} transmitter", transmitter.name());
         Radio {
-            script_path,
+            transmitter,
             command_handle: None,
-            frequency: 104.1,
-            playing: false,
+            decoder: None,
+            stream: None,
+            config: Config::default(),
+            playback: None,
+            upcoming: None,
+            retry_handle: None,
+            db,
+            offline_reason: None,
+        }
+    }
+
+    /// Radio used when the transmitter isn't available.
+    /// Songs aren't transmitted, but they're still "played" for their duration,
+    /// so the queue and the rest of the server keep working.
+    pub fn offline(reason: String, db: Addr<DBExecutor>) -> Self {
+        println!(
+            "Transmitter is not available, radio output is offline - {}",
+            reason
+        );
+        Radio {
+            offline_reason: Some(reason),
+            ..Radio::new(Box::new(NullTransmitter), db)
+        }
+    }
+
+    /// Starts transmitting active song from the second it was stopped at.
+    fn transmit(&mut self, ctx: &mut Context<Self>) -> Result<(), RadioError> {
+        if let Some(retry_handle) = self.retry_handle.take() {
+            ctx.cancel_future(retry_handle);
+        }
+        if self.config.is_streamed() {
+            return self.transmit_stream(ctx);
+        }
+        let playback = match self.playback.as_mut() {
+            Some(playback) => playback,
+            None => return Ok(()),
+        };
+        // transmitters can neither seek nor change volume, so song that doesn't start
+        // from the beginning or needs gain is decoded by ffmpeg and piped into the transmitter
+        let gain = playback.song.playback_gain();
+        let needs_decoding = playback.offset > 0 || gain != 0.0;
+        let mut decoder = if needs_decoding && self.transmitter.needs_audio() {
+            Some(audio::decoder(&playback.song.path, playback.offset, gain).spawn()?)
+        } else {
+            None
+        };
+        let audio = if decoder.is_some() {
+            "-"
+        } else {
+            &playback.song.path
+        };
+        let mut command = self.transmitter.command(&Transmission {
+            audio,
+            frequency: self.config.frequency,
+            duration: Some(playback.song.duration - playback.offset),
+            rds: get_rds(&self.config, &playback.song),
+        });
+        if let Some(stdout) = decoder.as_mut().and_then(|decoder| decoder.stdout.take()) {
+            command.stdin(stdout);
+        }
+        command.stderr(Stdio::piped());
+        // spawn command playing song on the radio
+        // it is async, so if I will cancel the future
+        // tokio will drop the command's process
+        // therefore it is useful for skipping and pausing logic
+        let child = match command.spawn_async() {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(mut decoder) = decoder {
+                    let _ = decoder.kill();
+                    let _ = decoder.wait();
+                }
+                return Err(e.into());
+            }
+        };
+        let future = wrap_future::<_, Self>(child.wait_with_output()).then(|output, radio, ctx| {
+            // transmitter is done, there's nothing left to cancel except the decoder
+            radio.command_handle = None;
+            match output {
+                Ok(ref output) if is_normal_exit(output.status) => {
+                    radio.stop_transmission(ctx);
+                    if let Some(playback) = radio.playback.take() {
+                        radio.play_next(ctx, playback.queue_addr);
+                    }
+                }
+                Ok(output) => radio.handle_failure(
+                    ctx,
+                    RadioError::TransmitterExited(describe_exit(output.status, &output.stderr)),
+                ),
+                Err(e) => radio.handle_failure(ctx, e.into()),
+            }
+            actix::fut::ok(())
+        });
+        playback.started_at = Some(Instant::now());
+        self.decoder = decoder;
+        self.command_handle = Some(ctx.spawn(future));
+        Ok(())
+    }
+
+    /// Starts the stream with active song, followed by the upcoming one.
+    fn transmit_stream(&mut self, ctx: &mut Context<Self>) -> Result<(), RadioError> {
+        let playback = match self.playback.as_mut() {
+            Some(playback) => playback,
+            None => return Ok(()),
+        };
+        let command = self.transmitter.command(&Transmission {
+            audio: "-",
+            frequency: self.config.frequency,
+            duration: None,
+            rds: get_rds(&self.config, &playback.song),
+        });
+        let stream = Stream::start(
+            command,
+            ctx.address(),
+            StreamOptions {
+                crossfade: self.config.crossfade,
+                needs_audio: self.transmitter.needs_audio(),
+                control_pipe: self.transmitter.control_pipe().map(str::to_owned),
+            },
+        )?;
+        stream.play(get_stream_song(
+            &self.config,
+            &playback.song,
+            playback.offset,
+        ));
+        if let Some(upcoming) = self.upcoming.as_ref() {
+            stream.play(get_stream_song(&self.config, &upcoming.song, 0));
+        }
+        playback.started_at = Some(Instant::now());
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Stops transmitting active song, but remembers where it was stopped,
+    /// so it can be transmitted again later.
+    /// Returns None if there's no song on air.
+    fn halt(&mut self, ctx: &mut Context<Self>) -> Option<PlaybackPosition> {
+        let position = match self.playback.as_mut() {
+            Some(playback) if playback.started_at.is_some() => {
+                playback.offset = playback.elapsed();
+                playback.started_at = None;
+                playback.position()
+            }
+            _ => return None,
+        };
+        self.stop_transmission(ctx);
+        Some(position)
+    }
+
+    /// Retries the failed transmission after a delay growing with every failure.
+    /// Song that transmitter or its decoder kept failing on is marked as broken and the next one is played instead.
+    /// Songs aren't marked as broken if transmitter couldn't even start,
+    /// since it's not their fault, those are retried until it starts.
+    fn handle_failure(&mut self, ctx: &mut Context<Self>, error: RadioError) {
+        println!("transmission failed - {}", error);
+        self.stop_transmission(ctx);
+        let playback = match self.playback.as_mut() {
+            Some(playback) => playback,
+            None => return,
+        };
+        if playback.started_at.is_some() {
+            playback.offset = playback.elapsed();
+            playback.started_at = None;
+        }
+        playback.failures += 1;
+        let song_id = playback.song.id;
+        let is_broken = match error {
+            RadioError::TransmitterExited(_) | RadioError::DecoderFailed(_) => {
+                playback.failures >= MAX_FAILURES
+            }
+            _ => false,
+        };
+        let retry_in = if is_broken {
+            None
+        } else {
+            Some(
+                RETRY_DELAY
+                    .checked_mul(2u32.saturating_pow(playback.failures - 1))
+                    .unwrap_or(MAX_RETRY_DELAY)
+                    .min(MAX_RETRY_DELAY),
+            )
+        };
+        ClientPublisher::from_registry().do_send(UserMessage::<TransmissionFailure> {
+            success: false,
+            action: "radio_error".to_owned(),
+            value: TransmissionFailure {
+                song_id,
+                error: error.to_string(),
+                retry_in: retry_in.map(|delay| delay.as_secs()),
+            },
+        });
+        match retry_in {
+            Some(delay) => {
+                self.retry_handle = Some(ctx.run_later(delay, |radio, ctx| {
+                    radio.retry_handle = None;
+                    if let Err(e) = radio.transmit(ctx) {
+                        radio.handle_failure(ctx, e);
+                    }
+                }));
+            }
+            None => {
+                self.db.do_send(MarkSongBroken { song_id });
+                self.give_up(ctx);
+            }
+        }
+    }
+
+    /// Drops the song on air, upcoming song takes its place if there's any.
+    fn give_up(&mut self, ctx: &mut Context<Self>) {
+        if let Some(playback) = self.playback.take() {
+            self.play_next(ctx, playback.queue_addr);
+        }
+    }
+
+    /// Plays the upcoming song if there's any, asks the queue for the next one otherwise.
+    fn play_next(&mut self, ctx: &mut Context<Self>, queue_addr: Addr<SongQueue>) {
+        if !self.play_upcoming(ctx) {
+            queue_addr.do_send(NextSong {});
+        }
+    }
+
+    /// Puts the upcoming song on air, returns false if there's none.
+    fn play_upcoming(&mut self, ctx: &mut Context<Self>) -> bool {
+        let upcoming = match self.upcoming.take() {
+            Some(upcoming) => upcoming,
+            None => return false,
+        };
+        // queue moved on to the song when it was handed over, clients are told that it's on air only now
+        upcoming.queue_addr.do_send(BroadcastState {});
+        self.playback = Some(upcoming);
+        if let Err(e) = self.transmit(ctx) {
+            self.handle_failure(ctx, e);
+        }
+        true
+    }
+
+    /// Kills processes transmitting the song.
+    fn stop_transmission(&mut self, ctx: &mut Context<Self>) {
+        if let Some(command_handle) = self.command_handle.take() {
+            // cancel future and drop the command proccess
+            ctx.cancel_future(command_handle);
+        }
+        if let Some(retry_handle) = self.retry_handle.take() {
+            ctx.cancel_future(retry_handle);
+        }
+        if let Some(mut decoder) = self.decoder.take() {
+            let _ = decoder.kill();
+            let _ = decoder.wait();
+        }
+        if let Some(stream) = self.stream.take() {
+            stream.stop();
         }
     }
 }
 
+/// RDS data of given song.
+fn get_rds<'a>(config: &'a Config, song: &Song) -> Rds<'a> {
+    Rds {
+        station_name: &config.station_name,
+        pi_code: &config.pi_code,
+        radio_text: config.render_radio_text(song),
+    }
+}
+
+fn get_stream_song(config: &Config, song: &Song, offset: i32) -> StreamSong {
+    StreamSong {
+        song_id: song.id,
+        path: song.path.clone(),
+        offset,
+        duration: song.duration,
+        gain: song.playback_gain(),
+        radio_text: config.render_radio_text(song),
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum RadioError {
+    #[fail(display = "couldn't start the transmission: {}", _0)]
+    Transmission(#[cause] io::Error),
+    #[fail(display = "{}", _0)]
+    TransmitterExited(String),
+    #[fail(display = "{}", _0)]
+    DecoderFailed(String),
+    #[fail(display = "invalid config: {}", _0)]
+    InvalidConfig(String),
+}
+
+impl From<io::Error> for RadioError {
+    fn from(error: io::Error) -> Self {
+        RadioError::Transmission(error)
+    }
+}
+
+impl ResponseError for RadioError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            RadioError::Transmission(_)
+            | RadioError::TransmitterExited(_)
+            | RadioError::DecoderFailed(_) => HttpResponse::InternalServerError(),
+            RadioError::InvalidConfig(_) => HttpResponse::BadRequest(),
+        }
+        .json(get_standard_failure_response())
+    }
+}
+
 pub struct PlaySong {
     pub song: Song,
     pub queue_addr: Addr<SongQueue>,
@@ -50,6 +439,17 @@ impl Message for SkipSong {
     type Result = ();
 }
 
+/// Cuts off the song on air along with the song handed over to the stream to be played after it,
+/// so the next song of the queue goes on air right away.
+/// Returns the song that was handed over, queue already moved past it.
+pub struct InterruptSong {
+    pub queue_addr: Addr<SongQueue>,
+}
+
+impl Message for InterruptSong {
+    type Result = Option<Song>;
+}
+
 impl Message for PlaySong {
     type Result = ();
 }
@@ -62,84 +462,245 @@ impl Actor for Radio {
 impl Handler<PlaySong> for Radio {
     type Result = ();
     fn handle(&mut self, msg: PlaySong, ctx: &mut Self::Context) -> Self::Result {
-        self.playing = true;
-        // spawn command playing song on the radio
-        // it is async, so if I will cancel the future
-        // tokio will drop the command's process
-        // therefore it is useful for skipping logic
-        let handle = Command::new("timeout")
-            .arg(&msg.song.duration.to_string())
-            .arg("sudo")
-            .arg(self.script_path.clone())
-            .arg("--freq")
-            // replace . with , because that's what library
-            .arg(self.frequency.to_string().replace(".", ","))
-            .arg("--audio")
-            .arg(&msg.song.path)
-            .spawn_async();
-
-        let future = handle
-            .expect("failed to spawn")
-            .map(move |_| {
-                msg.queue_addr.do_send(NextSong {});
-            })
-            .map_err(|e| panic!("failed to wait for exit: {}", e));
-        self.command_handle = Some(ctx.spawn(wrap_future::<_, Self>(future)));
+        let playback = Playback {
+            song: msg.song,
+            queue_addr: msg.queue_addr,
+            offset: 0,
+            started_at: None,
+            failures: 0,
+        };
+        if let Some(stream) = self.stream.as_ref() {
+            // stream is on air, song will be mixed in once the one on air is over
+            stream.play(get_stream_song(&self.config, &playback.song, 0));
+            self.upcoming = Some(playback);
+            return;
+        }
+        self.stop_transmission(ctx);
+        // queue picked the song itself, so the one left from the stream isn't played anymore
+        self.upcoming = None;
+        self.playback = Some(playback);
+        if let Err(e) = self.transmit(ctx) {
+            self.handle_failure(ctx, e);
+        }
     }
 }
 
 impl Handler<SkipSong> for Radio {
     type Result = ();
     fn handle(&mut self, msg: SkipSong, ctx: &mut Self::Context) -> Self::Result {
-        if let Some(command_handle) = self.command_handle {
-            // cancel future and drop the command proccess
-            ctx.cancel_future(command_handle);
-            msg.queue_addr.do_send(NextSong {});
+        if let Some(stream) = self.stream.as_ref() {
+            if self.playback.take().is_some() {
+                stream.skip();
+                // upcoming song takes over right away, otherwise ask for a new one
+                if self.upcoming.is_none() {
+                    msg.queue_addr.do_send(NextSong {});
+                }
+            }
+            return;
+        }
+        // paused song can be skipped as well, song handed over to the stream before it was paused takes over then
+        if self.playback.take().is_some() {
+            self.stop_transmission(ctx);
+            self.play_next(ctx, msg.queue_addr);
         }
     }
 }
 
-pub struct SetFrequency {
-    pub frequency: f32,
+impl Handler<InterruptSong> for Radio {
+    type Result = Option<Song>;
+    fn handle(&mut self, msg: InterruptSong, ctx: &mut Self::Context) -> Self::Result {
+        let upcoming = self.upcoming.take().map(|upcoming| upcoming.song);
+        let on_air = self.playback.take().is_some();
+        if !on_air && upcoming.is_none() {
+            return None;
+        }
+        match self.stream.as_ref() {
+            Some(stream) => stream.clear(),
+            None => self.stop_transmission(ctx),
+        }
+        msg.queue_addr.do_send(NextSong {});
+        upcoming
+    }
 }
 
-impl Message for SetFrequency {
-    type Result = ();
+/// Pause active song, returns position it was paused at.
+pub struct PauseSong;
+
+impl Message for PauseSong {
+    type Result = Result<PlaybackPosition, ()>;
 }
 
-impl Handler<SetFrequency> for Radio {
+impl Handler<PauseSong> for Radio {
+    type Result = Result<PlaybackPosition, ()>;
+    fn handle(&mut self, _msg: PauseSong, ctx: &mut Self::Context) -> Self::Result {
+        self.halt(ctx).ok_or(())
+    }
+}
+
+/// Resume paused song from the position it was paused at.
+pub struct ResumeSong;
+
+impl Message for ResumeSong {
+    type Result = Result<PlaybackPosition, ()>;
+}
+
+impl Handler<ResumeSong> for Radio {
+    type Result = Result<PlaybackPosition, ()>;
+    fn handle(&mut self, _msg: ResumeSong, ctx: &mut Self::Context) -> Self::Result {
+        match self.playback.as_ref() {
+            Some(playback) if playback.started_at.is_none() => {}
+            _ => return Err(()),
+        }
+        if let Err(e) = self.transmit(ctx) {
+            self.handle_failure(ctx, e);
+            return Err(());
+        }
+        self.playback.as_ref().map(Playback::position).ok_or(())
+    }
+}
+
+/// Keeps track of what's on air while songs are streamed.
+impl Handler<StreamEvent> for Radio {
     type Result = ();
+    fn handle(&mut self, msg: StreamEvent, ctx: &mut Self::Context) -> Self::Result {
+        if self.stream.is_none() {
+            // event sent by the stream right before it was stopped
+            return;
+        }
+        match msg {
+            StreamEvent::Started => {
+                if let Some(upcoming) = self.upcoming.take() {
+                    self.playback = Some(upcoming);
+                }
+                if let Some(playback) = self.playback.as_mut() {
+                    playback.started_at = Some(Instant::now());
+                }
+            }
+            StreamEvent::NeedNext => {
+                if let (Some(playback), None) = (self.playback.as_ref(), self.upcoming.as_ref()) {
+                    playback.queue_addr.do_send(NextSong {});
+                }
+            }
+            StreamEvent::Finished => {
+                self.playback = None;
+            }
+            StreamEvent::DecoderFailed { song_id, error } => {
+                // song is retried from where it stopped and marked as broken if it keeps failing
+                if self.playback.as_ref().map(|playback| playback.song.id) == Some(song_id) {
+                    self.handle_failure(ctx, RadioError::DecoderFailed(error));
+                }
+            }
+            StreamEvent::Failed(e) => {
+                // transmitter that stopped on its own is the reason why the stream failed
+                let error = match self.stream.take().and_then(Stream::stop) {
+                    Some(exit) => RadioError::TransmitterExited(exit),
+                    None => RadioError::Transmission(io::Error::other(e)),
+                };
+                self.handle_failure(ctx, error);
+            }
+        }
+    }
+}
+
+/// Get position of the active song, None if nothing is played.
+pub struct GetPlaybackPosition;
 
-    fn handle(&mut self, msg: SetFrequency, ctx: &mut Self::Context) -> Self::Result {
-        self.frequency = msg.frequency;
+impl Message for GetPlaybackPosition {
+    type Result = Option<PlaybackPosition>;
+}
+
+impl Handler<GetPlaybackPosition> for Radio {
+    type Result = Option<PlaybackPosition>;
+    fn handle(&mut self, _msg: GetPlaybackPosition, _ctx: &mut Self::Context) -> Self::Result {
+        self.playback.as_ref().map(Playback::position)
     }
 }
 
-pub struct GetFrequency;
+/// Get status of the radio's output.
+pub struct GetRadioStatus;
 
-impl Message for GetFrequency {
-    type Result = f32;
+impl Message for GetRadioStatus {
+    type Result = RadioStatus;
 }
 
-impl Handler<GetFrequency> for Radio {
-    type Result = f32;
+impl Handler<GetRadioStatus> for Radio {
+    type Result = MessageResult<GetRadioStatus>;
 
-    fn handle(&mut self, msg: GetFrequency, ctx: &mut Self::Context) -> Self::Result {
-        self.frequency
+    fn handle(&mut self, _msg: GetRadioStatus, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(RadioStatus {
+            transmitter: self.transmitter.name(),
+            online: self.offline_reason.is_none(),
+            offline_reason: self.offline_reason.clone(),
+        })
     }
 }
 
-/// Check if PiFmAdv script exists if not then panic.
-pub fn get_script_path() -> Result<String, ()> {
-    let path = Path::new("../PiFmAdv/src/pi_fm_adv");
-    let script_exists = path.exists();
-    if script_exists {
-        Ok(std::fs::canonicalize(&path)
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_owned())
-    } else {
-        Err(())
+/// Update radio's config, returns the config after the update.
+pub struct SetConfig {
+    pub update: ConfigUpdate,
+}
+
+impl Message for SetConfig {
+    type Result = Result<Config, RadioError>;
+}
+
+/// Applies the update and moves active song onto the new config, starting where it was.
+/// If the song can't be transmitted with the new config, the old one is brought back.
+impl Handler<SetConfig> for Radio {
+    type Result = Result<Config, RadioError>;
+
+    fn handle(&mut self, msg: SetConfig, ctx: &mut Self::Context) -> Self::Result {
+        let config = msg.update.apply(self.config.clone());
+        config.validate().map_err(RadioError::InvalidConfig)?;
+        // schedule, queue order, skip threshold, autoplay, request limits and max downloads are followed by the queue
+        // and the downloads, so transmission doesn't have to be restarted for them
+        let queue_only = Config {
+            schedule: self.config.schedule.clone(),
+            queue_order: self.config.queue_order,
+            skip_threshold: self.config.skip_threshold,
+            autoplay: self.config.autoplay.clone(),
+            request_limits: self.config.request_limits.clone(),
+            max_downloads: self.config.max_downloads,
+            ..config.clone()
+        } == self.config;
+        if queue_only {
+            self.config = config;
+            return Ok(self.config.clone());
+        }
+        let previous_config = std::mem::replace(&mut self.config, config);
+        if self.halt(ctx).is_none() {
+            // nothing is on air, new config will be used with the next song
+            // or with the next attempt of the failed transmission
+            if self.retry_handle.is_none() {
+                self.stop_transmission(ctx);
+            }
+            // stream stopped between two songs, the upcoming one would never start otherwise
+            if self.playback.is_none() {
+                self.play_upcoming(ctx);
+            }
+            return Ok(self.config.clone());
+        }
+        if let Err(e) = self.transmit(ctx) {
+            self.config = previous_config;
+            if let Err(e) = self.transmit(ctx) {
+                self.handle_failure(ctx, e);
+            }
+            return Err(e);
+        }
+        Ok(self.config.clone())
+    }
+}
+
+pub struct GetConfig;
+
+impl Message for GetConfig {
+    type Result = Config;
+}
+
+impl Handler<GetConfig> for Radio {
+    type Result = MessageResult<GetConfig>;
+
+    fn handle(&mut self, _msg: GetConfig, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.config.clone())
     }
 }
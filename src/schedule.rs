@@ -0,0 +1,67 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+/// Part of the weekday during which the radio is on air.
+pub struct OnAirWindow {
+    // e.g "Mon" or "monday"
+    pub weekday: Weekday,
+    // HH:MM:SS
+    pub start: NaiveTime,
+    // window that ends at or before its start lasts until the next day
+    pub end: NaiveTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+/// Windows during which the radio is on air, radio is on air all the time if there are none.
+pub struct Schedule(pub Vec<OnAirWindow>);
+
+impl OnAirWindow {
+    /// Start and end of the window if it starts on given date.
+    fn on(&self, date: NaiveDate) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        if date.weekday() != self.weekday {
+            return None;
+        }
+        let start = date.and_time(self.start);
+        let end = if self.end > self.start {
+            date.and_time(self.end)
+        } else {
+            date.succ().and_time(self.end)
+        };
+        Some((start, end))
+    }
+}
+
+impl Schedule {
+    /// Whether the radio should be on air at given time.
+    pub fn is_on_air(&self, time: NaiveDateTime) -> bool {
+        if self.0.is_empty() {
+            return true;
+        }
+        // window that started yesterday might still last
+        let dates = [time.date().pred(), time.date()];
+        self.0.iter().any(|window| {
+            dates
+                .iter()
+                .filter_map(|date| window.on(*date))
+                .any(|(start, end)| start <= time && time < end)
+        })
+    }
+
+    /// When the radio goes on or off air after given time, None if it never does.
+    pub fn next_change(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let on_air = self.is_on_air(time);
+        let mut boundaries: Vec<NaiveDateTime> = (-1..=7)
+            .map(|days| time.date() + Duration::days(days))
+            .flat_map(|date| self.0.iter().filter_map(move |window| window.on(date)))
+            .flat_map(|(start, end)| vec![start, end])
+            .filter(|boundary| *boundary > time)
+            .collect();
+        boundaries.sort();
+        // adjacent windows share boundaries at which nothing changes
+        boundaries
+            .into_iter()
+            .find(|boundary| self.is_on_air(*boundary) != on_air)
+    }
+}
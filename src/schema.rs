@@ -7,5 +7,72 @@ table! {
         thumbnail_url -> Text,
         artists -> Text,
         nsfw -> Bool,
+        gain -> Nullable<Float>,
+        broken -> Bool,
+        source_url -> Nullable<Text>,
     }
 }
+
+table! {
+    queued_songs (uuid) {
+        uuid -> Text,
+        song_id -> Integer,
+        requested_at -> Timestamp,
+        active -> Bool,
+        requested_by -> Nullable<Text>,
+    }
+}
+
+table! {
+    play_history (id) {
+        id -> Integer,
+        song_id -> Integer,
+        started_at -> Timestamp,
+        ended_at -> Nullable<Timestamp>,
+        skipped -> Bool,
+        source -> Text,
+        score -> Integer,
+        interrupted -> Bool,
+    }
+}
+
+table! {
+    playlists (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+table! {
+    playlist_songs (id) {
+        id -> Integer,
+        playlist_id -> Integer,
+        song_id -> Integer,
+        position -> Integer,
+    }
+}
+
+table! {
+    timed_entries (id) {
+        id -> Integer,
+        song_id -> Nullable<Integer>,
+        playlist_id -> Nullable<Integer>,
+        at -> Timestamp,
+        weekdays -> Nullable<Text>,
+        interrupt -> Bool,
+    }
+}
+
+joinable!(queued_songs -> songs (song_id));
+joinable!(play_history -> songs (song_id));
+joinable!(playlist_songs -> playlists (playlist_id));
+joinable!(playlist_songs -> songs (song_id));
+
+allow_tables_to_appear_in_same_query!(
+    play_history,
+    playlist_songs,
+    playlists,
+    queued_songs,
+    songs,
+    timed_entries,
+);
@@ -0,0 +1,136 @@
+use crate::download::DownloadError;
+use crate::io::SearchSongs;
+use crate::responses::get_standard_failure_response;
+use crate::song::SOCKET_TIMEOUT_SECS;
+use crate::system::AppState;
+use actix_web::{
+    AsyncResponder, Error as AWError, FutureResponse, HttpResponse, Query, ResponseError, State,
+};
+use failure::Fail;
+use futures::future::Future;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// How many videos are returned unless the client asks for a different number.
+const DEFAULT_SEARCH_RESULTS: usize = 5;
+/// youtube-dl fetches informations about every video one by one, so it takes a while for many of them.
+const MAX_SEARCH_RESULTS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Video found on youtube, its url can be requested as a song.
+pub struct SearchResult {
+    pub title: String,
+    pub uploader: Option<String>,
+    // in seconds, live streams don't have it
+    pub duration: Option<f64>,
+    pub thumbnail: Option<String>,
+    #[serde(rename(deserialize = "webpage_url"))]
+    pub url: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SearchResults {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SearchFailed {
+    pub query: String,
+    pub reason: &'static str,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+impl SearchQuery {
+    /// Search the IO worker can run, error if there is nothing to search for.
+    pub fn into_search(self) -> Result<SearchSongs, SearchError> {
+        let query = self.q.trim();
+        if query.is_empty() {
+            return Err(SearchError::EmptyQuery);
+        }
+        Ok(SearchSongs {
+            query: query.to_owned(),
+            limit: self
+                .limit
+                .unwrap_or(DEFAULT_SEARCH_RESULTS)
+                .clamp(1, MAX_SEARCH_RESULTS),
+        })
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum SearchError {
+    #[fail(display = "search query can't be empty")]
+    EmptyQuery,
+    #[fail(display = "search failed: {}", _0)]
+    Failed(#[cause] DownloadError),
+}
+
+impl From<DownloadError> for SearchError {
+    fn from(error: DownloadError) -> Self {
+        SearchError::Failed(error)
+    }
+}
+
+impl ResponseError for SearchError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SearchError::EmptyQuery => HttpResponse::BadRequest(),
+            SearchError::Failed(_) => HttpResponse::BadGateway(),
+        }
+        .json(get_standard_failure_response())
+    }
+}
+
+/// Searches youtube via youtube-dl, only informations about the videos are fetched, nothing is downloaded.
+pub fn search_youtube(query: &str, limit: usize) -> Result<Vec<SearchResult>, DownloadError> {
+    let output = Command::new("youtube-dl")
+        // informations about every video are printed as json in a separate line
+        .arg("--dump-json")
+        // video that can't be accessed doesn't stop the search
+        .arg("--ignore-errors")
+        .arg("--socket-timeout")
+        .arg(SOCKET_TIMEOUT_SECS.to_string())
+        .arg("--")
+        .arg(format!("ytsearch{}:{}", limit, query))
+        .output()
+        .map_err(|e| DownloadError::Failed(e.to_string()))?;
+    let results = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(serde_json::from_str::<SearchResult>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| DownloadError::Parse(e.to_string()))?;
+    // with --ignore-errors youtube-dl fails even if just one of the videos couldn't be accessed
+    if results.is_empty() && !output.status.success() {
+        return Err(DownloadError::from_output(&String::from_utf8_lossy(
+            &output.stderr,
+        )));
+    }
+    Ok(results)
+}
+
+// API functions
+/// GET /search?q={query}&limit={limit}
+pub fn search_songs(
+    query: Query<SearchQuery>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    let search = match query.into_inner().into_search() {
+        Ok(search) => search,
+        Err(e) => return Box::new(futures::future::err(AWError::from(e))),
+    };
+    let query = search.query.clone();
+    state
+        .search
+        .send(search)
+        .from_err()
+        .and_then(|res| res.map_err(|e| AWError::from(SearchError::from(e))))
+        .map(|results| HttpResponse::Ok().json(SearchResults { query, results }))
+        .responder()
+}
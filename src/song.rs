@@ -1,23 +1,60 @@
-use super::db::{DeleteSong, GetAllSongs, GetRandomSong, ToggleSongNsfw};
+use super::audio::{measure_gain, probe_duration};
+use super::db::{DeleteSong, GetAllSongs, GetSongsWithoutGain, SetSongGain, ToggleSongNsfw};
+use super::download::{
+    parse_progress, DownloadError, DownloadStatus, Downloads, FinishDownload, SetDownloadProgress,
+    StartDownload,
+};
+use super::io::MeasureGain;
+use super::responses::get_standard_failure_response;
 use super::schema::songs;
+use super::song_queue::QueueJob;
 use super::system::AppState;
+use actix::{Addr, Arbiter, SystemService};
 use actix_web::{AsyncResponder, Error as AWError, FutureResponse, HttpResponse, Path, State};
 use chrono::prelude::*;
+use diesel::result::Error as DieselError;
 use diesel::{Insertable, Queryable};
-use futures::future::Future;
+use futures::future::{ok, Either, Future};
+use futures::stream::{self, Stream};
 use serde::{self, Deserialize, Serialize};
 use serde_json::json;
+use std::collections::VecDeque;
 use std::fs;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read};
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long youtube-dl waits for data before it gives up.
+pub const SOCKET_TIMEOUT_SECS: u32 = 30;
+/// How long the whole download can take, youtube-dl is killed afterwards.
+/// Socket timeout doesn't help if the site keeps sending the data, just very slowly.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How many of the last lines youtube-dl printed to stderr are kept, the error is printed at the end.
+const STDERR_TAIL_LINES: usize = 20;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+/// Song is searched for on youtube by its name and artists, unless its url is given.
+/// Name and artists of the song requested by its url are taken from the video if they're left empty.
 pub struct SongRequest {
+    #[serde(default)]
     pub artists: String,
+    #[serde(default)]
     pub name: String,
+    // direct link to the song, e.g on youtube, soundcloud or bandcamp
+    #[serde(default)]
+    pub url: Option<String>,
     #[serde(skip_deserializing, default = "now")]
     pub requested_at: DateTime<Utc>,
+    // address of the client that requested the song, set by the websocket
+    #[serde(skip)]
+    pub requested_by: Option<String>,
+    #[serde(default = "no_thumbnail")]
     thumbnail_url: String,
     pub nsfw: bool,
 }
@@ -26,23 +63,70 @@ fn now() -> DateTime<Utc> {
     Utc::now()
 }
 
+fn no_thumbnail() -> String {
+    "none".to_owned()
+}
+
 impl SongRequest {
     /// {song's name} - {song's artists separated by ", " }
     pub fn get_formatted_name(&self) -> String {
         format!("{} - {}", self.name, self.artists)
     }
+
+    /// Whether the song can be looked for, it needs either a name or a http(s) url.
+    pub fn is_valid(&self) -> bool {
+        match &self.url {
+            Some(url) => url.starts_with("https://") || url.starts_with("http://"),
+            None => !self.name.trim().is_empty(),
+        }
+    }
+
+    /// What youtube-dl downloads, either the url or the first search result.
+    fn get_source(&self) -> String {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => format!("ytsearch1:{}", self.get_formatted_name()),
+        }
+    }
+
+    /// Name of the song's files inside static/songs. Id of the download is used if the song was requested by its url
+    /// or files with the song's name already exist, so the download never overwrites or removes files of another song.
+    fn get_file_name(&self, job_id: Uuid) -> String {
+        let formatted_name = self.get_formatted_name();
+        if self.url.is_none()
+            && !self.name.is_empty()
+            && get_files_named(&formatted_name).is_empty()
+        {
+            formatted_name
+        } else {
+            job_id.to_string()
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Queryable)]
 pub struct Song {
-    id: i32,
+    pub id: i32,
     pub name: String,
     #[serde(skip_serializing)]
     pub path: String,
     pub duration: i32,
     thumbnail_url: String,
-    artists: String,
+    pub artists: String,
     nsfw: bool,
+    // gain in dB applied at playback, None if song's loudness wasn't measured yet
+    pub gain: Option<f32>,
+    // whether transmitter kept failing on the song
+    broken: bool,
+    // link the song was downloaded from if it was requested by its url
+    pub source_url: Option<String>,
+}
+
+impl Song {
+    /// Gain in dB applied when song is played.
+    pub fn playback_gain(&self) -> f32 {
+        self.gain.unwrap_or(0.0)
+    }
 }
 
 #[derive(Insertable, Clone, Debug)]
@@ -55,6 +139,8 @@ pub struct NewSong {
     // , separated array
     pub artists: String,
     nsfw: bool,
+    gain: Option<f32>,
+    source_url: Option<String>,
 }
 
 /// Get song's path inside /static/songs.
@@ -68,73 +154,258 @@ fn get_json_path(song_path: &str) -> String {
     format!("{}.info.json", song_path)
 }
 
-/// Downloads song from youtube via youtube-dl and returns boolean  whether song was downloaded or not.
-pub fn download_song(requested_song: &SongRequest) -> Result<NewSong, ()> {
-    let song_path = get_song_path(&requested_song.get_formatted_name());
-    let search_query: &str = &format!("ytsearch1:{}", &requested_song.get_formatted_name());
-    println!("{}, {}", song_path, &requested_song.get_formatted_name());
-    let output = Command::new("youtube-dl")
-        // download one song from youtube
+/// Downloads song from youtube via youtube-dl, its progress is reported to the Downloads along the way.
+/// Files left behind by the download that failed or was cancelled are removed.
+pub fn download_song(requested_song: &SongRequest, job_id: Uuid) -> Result<NewSong, DownloadError> {
+    let downloads = Downloads::from_registry();
+    let song = fetch_song(requested_song, job_id, &downloads);
+    // loudness measurement counts toward the downloads' limit, so the next one starts only once it's done
+    downloads.do_send(FinishDownload { id: job_id });
+    song
+}
+
+/// Downloads the song and measures its loudness.
+fn fetch_song(
+    requested_song: &SongRequest,
+    job_id: Uuid,
+    downloads: &Addr<Downloads>,
+) -> Result<NewSong, DownloadError> {
+    let file_name = requested_song.get_file_name(job_id);
+    let song_path = get_song_path(&file_name);
+    println!("{}, {}", song_path, requested_song.get_source());
+    // decode duration from .info.json that youtube-dl downloads
+    let downloaded = run_youtube_dl(&requested_song.get_source(), &file_name, job_id, downloads)
+        .and_then(|_| get_song_info(&song_path));
+    let info = match downloaded {
+        Ok(info) => info,
+        Err(e) => {
+            println!("Error during downloading a song - {}", e);
+            remove_partial_files(&file_name);
+            return Err(e);
+        }
+    };
+    // music sites tell the track and the artist, otherwise the video's title and uploader are the best guess
+    let name = if requested_song.name.is_empty() {
+        info.track.or(info.title).unwrap_or_default()
+    } else {
+        requested_song.name.clone()
+    };
+    let artists = if requested_song.artists.is_empty() {
+        info.artist.or(info.uploader).unwrap_or_default()
+    } else {
+        requested_song.artists.clone()
+    };
+    // if there is no thumbnail specified use the one provided by youtube-dl
+    let thumbnail_url = if requested_song.thumbnail_url == "none" {
+        info.thumbnail.unwrap_or_else(no_thumbnail)
+    } else {
+        requested_song.thumbnail_url.clone()
+    };
+    let path = format!("{}.wav", song_path);
+    // duration of the downloaded file is read if the site didn't tell it
+    let duration = match info.duration.map_or_else(|| probe_duration(&path), Ok) {
+        Ok(duration) => duration,
+        Err(_) => {
+            remove_partial_files(&file_name);
+            return Err(DownloadError::Parse(
+                "song's duration is unknown".to_owned(),
+            ));
+        }
+    };
+    downloads.do_send(SetDownloadProgress {
+        id: job_id,
+        status: DownloadStatus::Measuring,
+        progress: 100.0,
+    });
+    // song that can't be measured is played as it is and picked up by the backfill later
+    let gain = measure_gain(&path).ok();
+    Ok(NewSong {
+        // some sites give duration in fractions of a second
+        duration: duration.round() as i32,
+        name,
+        artists,
+        thumbnail_url,
+        path,
+        nsfw: requested_song.nsfw,
+        gain,
+        source_url: requested_song.url.clone(),
+    })
+}
+
+/// Runs youtube-dl and reports its progress, returns error if it failed, took too long or the download was cancelled.
+fn run_youtube_dl(
+    source: &str,
+    file_name: &str,
+    job_id: Uuid,
+    downloads: &Addr<Downloads>,
+) -> Result<(), DownloadError> {
+    let child = Command::new("youtube-dl")
         .current_dir("./static/songs")
-        .arg(search_query)
+        // only the video is downloaded if its url points to a playlist as well
+        .arg("--no-playlist")
         // extract audio from the video and format it to mp3
         .arg("-x")
         .arg("--audio-format")
         .arg("wav")
         .arg("--output")
         // why not just use song_path? without %(ext)s weird things happen inside youtube-dl and it outputs not working on rpi working file
-        .arg(format!(
-            "{}.%(ext)s",
-            &requested_song.get_formatted_name().clone()
-        ))
+        .arg(format!("{}.%(ext)s", file_name))
         .arg("--write-info-json")
-        .output();
-    if output.is_ok() {
-        let info = get_song_info(&song_path, &requested_song.name).unwrap();
-        // if there is no thumbnail specified use the one provided by youtube-dl
-        let thumbnail_url = if requested_song.thumbnail_url == "none" {
-            info.thumbnail
-        } else {
-            requested_song.thumbnail_url.clone()
-        };
-        Ok(NewSong {
-            duration: info.duration,
-            name: requested_song.name.clone(),
-            artists: requested_song.artists.clone(),
-            thumbnail_url,
-            path: format!("{}.wav", song_path),
-            nsfw: requested_song.nsfw,
+        // every progress update is printed in a new line, so it can be read as it comes
+        .arg("--newline")
+        // stalled connection fails the download instead of holding up the worker
+        .arg("--socket-timeout")
+        .arg(SOCKET_TIMEOUT_SECS.to_string())
+        // url or search of the song, it can't be taken for an option even if it starts with a dash
+        .arg("--")
+        .arg(source)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return Err(DownloadError::Failed(e.to_string())),
+    };
+    let stdout = child.stdout.take();
+    // stderr is read as it comes, otherwise youtube-dl would block once the pipe is full
+    let stderr = child
+        .stderr
+        .take()
+        .map(|stderr| thread::spawn(move || read_tail(stderr)));
+    let process = Arc::new(Mutex::new(child));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let started = downloads
+        .send(StartDownload {
+            id: job_id,
+            process: process.clone(),
+            cancelled: cancelled.clone(),
         })
-    } else {
-        println!(
-            "Error during downloading a song - {:#?}",
-            String::from_utf8(output.unwrap().stderr)
-        );
-        Err(())
+        .wait()
+        .unwrap_or(false);
+    if !started {
+        // download was cancelled while it waited for the worker
+        let mut process = process.lock().unwrap();
+        if process.kill().is_ok() {
+            let _ = process.wait();
+        }
+        return Err(DownloadError::Cancelled);
+    }
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let (finished, watchdog) = mpsc::channel::<()>();
+    {
+        let process = process.clone();
+        let timed_out = timed_out.clone();
+        thread::spawn(move || {
+            // download finished in time if the sender is dropped before that
+            if let Err(RecvTimeoutError::Timeout) = watchdog.recv_timeout(DOWNLOAD_TIMEOUT) {
+                timed_out.store(true, Ordering::SeqCst);
+                let _ = process.lock().unwrap().kill();
+            }
+        });
+    }
+    if let Some(stdout) = stdout {
+        let mut reported = None;
+        // line that isn't valid UTF-8 doesn't stop the reading
+        for line in BufReader::new(stdout).split(b'\n').map_while(Result::ok) {
+            if let Some((status, progress)) = parse_progress(&String::from_utf8_lossy(&line)) {
+                // youtube-dl prints progress a lot more often than clients need it
+                if reported == Some((status, progress.floor())) {
+                    continue;
+                }
+                reported = Some((status, progress.floor()));
+                downloads.do_send(SetDownloadProgress {
+                    id: job_id,
+                    status,
+                    progress,
+                });
+            }
+        }
+    }
+    // stdout is closed once youtube-dl exits or is killed
+    drop(finished);
+    let status = process.lock().unwrap().wait();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) if timed_out.load(Ordering::SeqCst) => Err(DownloadError::Timeout),
+        Ok(_) if cancelled.load(Ordering::SeqCst) => Err(DownloadError::Cancelled),
+        // youtube-dl was killed by something else than the server
+        Ok(status) if status.code().is_none() => Err(DownloadError::Failed(
+            "youtube-dl was killed by a signal".to_owned(),
+        )),
+        Ok(_) => {
+            let error = stderr
+                .and_then(|stderr| stderr.join().ok())
+                .unwrap_or_default();
+            Err(DownloadError::from_output(&error))
+        }
+        Err(e) => Err(DownloadError::Failed(e.to_string())),
+    }
+}
+
+/// Paths of the files inside static/songs named {file_name}.{extension}.
+fn get_files_named(file_name: &str) -> Vec<PathBuf> {
+    let prefix = format!("{}.", file_name);
+    match fs::read_dir("static/songs") {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Reads everything until the stream is closed, only the last lines are returned.
+fn read_tail<R: Read>(stream: R) -> String {
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    for line in BufReader::new(stream).split(b'\n').map_while(Result::ok) {
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(String::from_utf8_lossy(&line).into_owned());
+    }
+    Vec::from(tail).join("\n")
+}
+
+/// Removes files youtube-dl left behind, e.g partially downloaded video.
+fn remove_partial_files(file_name: &str) {
+    for path in get_files_named(file_name) {
+        if let Err(e) = fs::remove_file(&path) {
+            println!("couldn't remove {:?} - {}", path, e);
+        }
     }
-    // decode duration from .info.json that youtube-dl downloads
 }
 
 #[derive(Serialize, Deserialize)]
 struct Info {
-    duration: i32,
-    thumbnail: String,
+    // not every site tells the duration or has a thumbnail
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    thumbnail: Option<String>,
+    title: Option<String>,
+    uploader: Option<String>,
+    // only filled in by some of the sites, e.g soundcloud or bandcamp
+    track: Option<String>,
+    artist: Option<String>,
 }
 
 /// Extracts informations from song.info.json saved by youtube-dl with informations about downloaded song.
-fn get_song_info(song_path: &str, song_name: &str) -> Result<Info, ()> {
+/// youtube-dl doesn't fail if the search has no results, so the missing file means that the song wasn't found.
+fn get_song_info(song_path: &str) -> Result<Info, DownloadError> {
     let json_path = get_json_path(song_path);
     let file = fs::File::open(&json_path);
     match file {
         Ok(file) => {
             let reader = BufReader::new(file);
-            let json_content: Info = serde_json::from_reader(reader).unwrap();
+            let json_content = serde_json::from_reader::<_, Info>(reader)
+                .map_err(|e| DownloadError::Parse(e.to_string()));
             fs::remove_file(json_path);
-            Ok(json_content)
+            json_content
         }
         e => {
             eprintln!("error during opening a file - {:#?}", e);
-            Err(())
+            Err(DownloadError::NotFound)
         }
     }
 }
@@ -166,17 +437,102 @@ pub fn toggle_song_nsfw(
         .responder()
 }
 
+/// POST /songs/normalize
+/// Measures loudness of every song that wasn't measured yet, e.g because it was downloaded
+/// before songs were normalized. Songs are measured in the background one by one,
+/// response only contains how many of them are going to be measured.
+pub fn normalize_songs(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    let db = state.db.clone();
+    let io = state.io.clone();
+    state
+        .db
+        .send(GetSongsWithoutGain {})
+        .from_err()
+        .and_then(move |songs| {
+            let songs = match songs {
+                Ok(songs) => songs,
+                Err(e) => {
+                    println!("couldn't load songs without gain - {}", e);
+                    return Ok(
+                        HttpResponse::InternalServerError().json(get_standard_failure_response())
+                    );
+                }
+            };
+            let count = songs.len();
+            // the next song is measured once the previous one is done,
+            // so downloads and searches don't wait for all of them in the IO workers' mailbox
+            let measurements = stream::iter_ok(songs).for_each(move |song| {
+                let db = db.clone();
+                io.send(MeasureGain {
+                    path: song.path.clone(),
+                })
+                .map_err(|e| println!("couldn't measure loudness - {}", e))
+                .and_then(move |gain| match gain {
+                    Ok(gain) => Either::A(
+                        db.send(SetSongGain {
+                            song_id: song.id,
+                            gain,
+                        })
+                        .map(|_| ())
+                        .map_err(|e| println!("couldn't save song's gain - {}", e)),
+                    ),
+                    Err(_) => Either::B(ok(())),
+                })
+            });
+            Arbiter::spawn(measurements);
+            Ok(HttpResponse::Ok().json(json!({ "songs": count })))
+        })
+        .responder()
+}
+
 #[derive(Deserialize)]
 pub struct SongId {
     id: i32,
 }
 
 /// DELETE /songs/{song_id}
+/// Song is removed from the queue as well, its history is kept.
 pub fn delete_song(path: Path<SongId>, state: State<AppState>) -> FutureResponse<HttpResponse> {
+    let queue = state.queue_handler.clone();
     state
         .db
         .send(DeleteSong { song_id: path.id })
-        .and_then(|song| Ok(HttpResponse::Ok().json(song.unwrap())))
         .from_err()
+        .map(move |song| match song {
+            Ok(song) => {
+                queue.do_send(QueueJob::RemoveDeletedSong { song_id: song.id });
+                HttpResponse::Ok().json(song)
+            }
+            Err(DieselError::NotFound) => {
+                HttpResponse::NotFound().json(get_standard_failure_response())
+            }
+            Err(e) => {
+                println!("couldn't delete the song - {}", e);
+                HttpResponse::InternalServerError().json(get_standard_failure_response())
+            }
+        })
         .responder()
 }
+
+/// Fixtures shared by the tests of the modules working with songs.
+#[cfg(test)]
+pub mod test_helpers {
+    use super::Song;
+    use serde_json::json;
+
+    /// Song by given artists that isn't nsfw, broken nor measured.
+    pub fn song(id: i32, artists: &str) -> Song {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": format!("song {}", id),
+            "path": format!("{}.wav", id),
+            "duration": 60,
+            "thumbnail_url": "none",
+            "artists": artists,
+            "nsfw": false,
+            "gain": null,
+            "broken": false,
+        }))
+        .unwrap()
+    }
+}
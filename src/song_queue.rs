@@ -1,22 +1,53 @@
+use super::autoplay::AutoplayConfig;
 use super::io::MyIO;
-use super::radio::{Radio, SkipSong};
+use super::radio::{
+    GetConfig, GetPlaybackPosition, GetRadioStatus, InterruptSong, PauseSong, PlaybackPosition,
+    Radio, RadioStatus, ResumeSong, SkipSong,
+};
 use super::song::Song;
-use crate::client_publisher::ClientPublisher;
-use crate::db::{CheckSongExistence, DBExecutor, GetRandomSong, SaveSong};
+use crate::client_publisher::{ClientPublisher, GetClientCount};
+use crate::db::{
+    CheckSongExistence, DBExecutor, DeleteQueuedSong, DeleteTimedEntry, FinishPlayedSong,
+    GetAutoplaySong, GetHistory, GetPlayedSongIds, GetPlaylist, GetQueuedSongs, GetSongBySourceUrl,
+    GetTimedEntries, GetTimedSongs, SaveQueuedSong, SaveSong, SetActiveQueuedSong,
+    SetTimedEntryTime, StartPlayedSong,
+};
+use crate::download::{AddDownload, DownloadError, DownloadFailed, DownloadJob, Downloads};
+use crate::history::{HistoryEntry, NewPlayedSong, PlaySource, SongEnd};
 use crate::io::IOJob::DownloadSong;
 use crate::radio;
-use crate::song::SongRequest;
-use crate::web_socket::{EmptyValue, UserMessage};
+use crate::schema::queued_songs;
+use crate::song::{NewSong, SongRequest};
+use crate::system::AppState;
+use crate::timed::TimedEntry;
+use crate::web_socket::{EmptyValue, MyWebSocket, UserMessage};
 use actix::fut::wrap_future;
 use actix::*;
+use actix_web::{AsyncResponder, FutureResponse, HttpResponse, Json, State};
 use chrono::prelude::*;
 use chrono::Utc;
-use futures::future::{ok as fut_ok, Future};
-use serde::Serialize;
+use diesel::{Insertable, Queryable};
+use futures::future::{ok as fut_ok, Either, Future};
+use futures::sync::oneshot;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use uuid::Uuid;
 
 type ActorContext = Context<SongQueue>;
 
+/// How often clients are told about the position of the active song.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// How often it's checked whether the radio should go on or off air.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(10);
+/// How many of the songs that were on air are sent along with the queue state.
+const RECENT_HISTORY: i64 = 5;
+/// How often it's checked whether any of the timed entries should be played.
+const TIMED_ENTRIES_INTERVAL: Duration = Duration::from_secs(1);
+/// Timed entry that is late by more than this, e.g because the server was down, isn't played.
+const MISSED_TIMED_ENTRY_SECS: i64 = 60;
+
 #[derive(Serialize, Clone, Debug)]
 pub struct ScheduledSong {
     song: Song,
@@ -26,6 +57,179 @@ pub struct ScheduledSong {
     requested_at: DateTime<Utc>,
     // uuid used to identify songs in queue in order to delete them
     uuid: Uuid,
+    // address of the client that requested the song, None if it's unknown
+    #[serde(skip_serializing)]
+    requested_by: Option<String>,
+    // sum of the votes, songs with higher score are played first
+    score: i32,
+    // vote of every websocket connection that voted on the song
+    #[serde(skip_serializing)]
+    votes: HashMap<Uuid, i32>,
+    // position the song was moved to by hand, None if it's placed by the order
+    #[serde(skip_serializing)]
+    pin: Option<Pin>,
+    // turn of the round robin order the song is played in, it's given once the song is added to the queue
+    #[serde(skip_serializing)]
+    turn: usize,
+}
+
+impl ScheduledSong {
+    fn new(song: Song, requested_at: DateTime<Utc>, requested_by: Option<String>) -> Self {
+        ScheduledSong {
+            song,
+            requested_at,
+            uuid: Uuid::new_v4(), // uuid to easily identify song e.g during deleting it from queue
+            requested_by,
+            score: 0,
+            votes: HashMap::new(),
+            pin: None,
+            turn: 0,
+        }
+    }
+}
+
+/// Turns of the round robin order, every client gets one song in each turn.
+#[derive(Default, Debug)]
+pub struct Turns {
+    // turn of the last song that was taken from the queue
+    round: usize,
+    // last turn every client got
+    last: HashMap<Option<String>, usize>,
+}
+
+impl Turns {
+    /// Turn of the next song of given client, it's the one after the client's last turn,
+    /// but client that didn't request anything for a while doesn't get the turns that are already over.
+    fn next(&mut self, requested_by: &Option<String>) -> usize {
+        let turn = self
+            .last
+            .get(requested_by)
+            .map_or(self.round, |last| (last + 1).max(self.round));
+        self.last.insert(requested_by.clone(), turn);
+        turn
+    }
+
+    /// Song of given turn was taken from the queue.
+    fn start(&mut self, turn: usize) {
+        self.round = self.round.max(turn);
+    }
+}
+
+/// Song moved by hand stays at its position regardless of the order and votes.
+#[derive(Clone, Debug)]
+struct Pin {
+    index: usize,
+    // song that was moved later takes the position if more songs were moved to it
+    moved_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+/// Order in which scheduled songs are played.
+pub enum QueueOrder {
+    /// Songs are played in the order they were requested in.
+    #[default]
+    Fifo,
+    /// Clients take turns, so one that requested a lot of songs doesn't hold up the others.
+    /// Songs of the same client are played in the order they were requested in.
+    RoundRobin,
+}
+
+impl QueueOrder {
+    /// Sorts songs in the order they should be played in.
+    fn sort(self, songs: &mut [ScheduledSong]) {
+        songs.sort_by_key(|song| song.requested_at);
+        if self == QueueOrder::RoundRobin {
+            // sort is stable, so songs in the same turn stay in the order they were requested in
+            songs.sort_by_key(|song| song.turn);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    /// Songs are played once.
+    #[default]
+    Off,
+    /// Song on air is played again until it's skipped.
+    One,
+    /// Songs from the queue are added back to its end once they're played.
+    Queue,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+/// How songs are taken from the queue.
+pub struct PlayMode {
+    pub repeat: RepeatMode,
+    // whether songs are taken from the queue at random instead of in order
+    pub shuffle: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Changes to the play mode, fields that are missing are left as they were.
+pub struct PlayModeUpdate {
+    pub repeat: Option<RepeatMode>,
+    pub shuffle: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+/// Limits of the songs requested by clients, songs that break them aren't added to the queue.
+pub struct RequestLimits {
+    // how many songs one client can have in the queue at once, unlimited if None
+    pub max_songs_per_client: Option<usize>,
+    // how many seconds all of the queued songs can last together, unlimited if None
+    pub max_queue_duration: Option<u32>,
+    // song that is queued or was on air in this many last minutes is rejected, duplicates are allowed if None
+    pub no_repeat_minutes: Option<u32>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_songs_per_client: Some(10),
+            max_queue_duration: None,
+            no_repeat_minutes: Some(30),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+/// Why the requested song wasn't added to the queue.
+pub enum RequestRejection {
+    /// Client already has as many songs in the queue as it can.
+    TooManySongs { limit: usize },
+    /// Song would make the queue last longer than it can.
+    QueueTooLong { limit: u32 },
+    /// Song is already in the queue or on air.
+    AlreadyQueued,
+    /// Song was on air recently.
+    RecentlyPlayed { minutes: u32 },
+}
+
+#[derive(Queryable, Insertable, Clone, Debug)]
+#[table_name = "queued_songs"]
+/// Scheduled song as it's saved in the database, so the queue survives restarts.
+pub struct QueuedSong {
+    uuid: String,
+    song_id: i32,
+    requested_at: NaiveDateTime,
+    // whether song is on air, it's played again first after restart
+    active: bool,
+    requested_by: Option<String>,
+}
+
+impl From<&ScheduledSong> for QueuedSong {
+    fn from(scheduled_song: &ScheduledSong) -> Self {
+        QueuedSong {
+            uuid: scheduled_song.uuid.to_string(),
+            song_id: scheduled_song.song.id,
+            requested_at: scheduled_song.requested_at.naive_utc(),
+            active: false,
+            requested_by: scheduled_song.requested_by.clone(),
+        }
+    }
 }
 
 pub struct SongQueue {
@@ -34,23 +238,98 @@ pub struct SongQueue {
     pub songs_queue: Vec<ScheduledSong>,
     pub radio: Addr<Radio>,
     pub active_song: Option<Song>,
+    // whether the radio is on air according to the schedule, None until it's checked
+    pub on_air: Option<bool>,
+    pub order: QueueOrder,
+    // part of connected clients that has to vote for skipping the song on air
+    pub skip_threshold: f32,
+    // connections that voted for skipping the song on air
+    pub skip_votes: HashSet<Uuid>,
+    // how songs are picked when the queue is empty
+    pub autoplay: AutoplayConfig,
+    pub play_mode: PlayMode,
+    // why the active song went on air, song played again keeps it
+    pub active_source: PlaySource,
+    // whether the active song was skipped or interrupted, it isn't played again in repeat-one mode then
+    pub skipped: bool,
+    // songs and playlists played at given time, the ones played first go first
+    pub timed_entries: Vec<TimedEntry>,
+    pub request_limits: RequestLimits,
+    pub turns: Turns,
+    // how many songs of every client are being downloaded, they count towards its limit
+    pub downloading: HashMap<Option<String>, usize>,
 }
 
 impl Actor for SongQueue {
     type Context = ActorContext;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.next_song(ctx);
+        self.load_queue(ctx);
+        ctx.run_interval(SCHEDULE_INTERVAL, |actor, ctx| actor.check_schedule(ctx));
+        ctx.run_interval(PROGRESS_INTERVAL, |actor, ctx| {
+            actor.broadcast_progress(ctx)
+        });
+        ctx.notify(ReloadTimedEntries {});
+        ctx.run_interval(TIMED_ENTRIES_INTERVAL, |actor, ctx| {
+            actor.check_timed_entries(ctx)
+        });
     }
 }
 
+#[derive(Serialize, Clone)]
+pub struct AirTime {
+    pub on_air: bool,
+    // when the radio goes on or off air next time, None if it never does
+    pub next_change: Option<NaiveDateTime>,
+}
+
 #[derive(Message, Debug)]
 pub enum QueueJob {
-    PlaySong { song: Song },
-    ScheduleSong { scheduled_song: ScheduledSong },
-    DownloadSong { requested_song: SongRequest },
-    SkipSong,
-    DeleteSongFromQueue { uuid: Uuid },
+    PlaySong {
+        song: Song,
+        source: PlaySource,
+        // sum of the votes song got while it was queued
+        score: i32,
+    },
+    ScheduleSong {
+        scheduled_song: ScheduledSong,
+    },
+    DownloadSong {
+        requested_song: SongRequest,
+        // websocket of the client, it's told if the song isn't added to the queue
+        requester: Addr<MyWebSocket>,
+    },
+    VoteSkip {
+        // id of the websocket connection
+        voter: Uuid,
+    },
+    // connection was closed, its vote for skipping the song on air doesn't count anymore
+    WithdrawSkipVote {
+        voter: Uuid,
+    },
+    VoteSong {
+        uuid: Uuid,
+        // id of the websocket connection
+        voter: Uuid,
+        vote: i32,
+    },
+    PauseSong,
+    ResumeSong,
+    DeleteSongFromQueue {
+        uuid: Uuid,
+    },
+    // song was deleted from the database, so it can't be played anymore
+    RemoveDeletedSong {
+        song_id: i32,
+    },
+    MoveSongInQueue {
+        uuid: Uuid,
+        index: usize,
+    },
+    EnqueuePlaylist {
+        playlist_id: i32,
+        requested_by: Option<String>,
+    },
 }
 
 impl Handler<QueueJob> for SongQueue {
@@ -65,6 +344,49 @@ pub struct NextSong {
     pub next_song: Song,
 }
 
+#[derive(Serialize, Clone)]
+/// How many clients want to skip the song on air and how many have to.
+pub struct SkipVotes {
+    pub song_id: i32,
+    pub votes: usize,
+    pub needed: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SongPosition {
+    pub uuid: Uuid,
+    pub index: usize,
+}
+
+#[derive(Serialize, Clone)]
+/// Songs that changed their position in the queue.
+pub struct QueueDiff {
+    pub positions: Vec<SongPosition>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PlaylistEnqueued {
+    pub playlist_id: i32,
+    // how many songs were added to the queue
+    pub songs: usize,
+    // songs left out, because they broke the request limits
+    pub skipped: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SongScore {
+    pub uuid: Uuid,
+    pub score: i32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RequestRejected {
+    pub name: String,
+    pub artists: String,
+    #[serde(flatten)]
+    pub rejection: RequestRejection,
+}
+
 impl SongQueue {
     // Send message to radio's actor with song to play.
     pub fn play_song(&mut self, ctx: &mut ActorContext, song: &Song) {
@@ -76,8 +398,17 @@ impl SongQueue {
 
     fn handle_activities(&mut self, ctx: &mut ActorContext, radio_job: QueueJob) {
         match radio_job {
-            QueueJob::PlaySong { song } => {
+            QueueJob::PlaySong {
+                song,
+                source,
+                score,
+            } => {
+                self.db.do_send(StartPlayedSong {
+                    song: NewPlayedSong::new(&song, source, score),
+                });
                 self.active_song = Some(song.clone());
+                self.active_source = source;
+                self.skip_votes.clear();
                 self.play_song(ctx, &song);
                 let response = UserMessage::<NextSong> {
                     success: true,
@@ -86,10 +417,27 @@ impl SongQueue {
                 };
                 ClientPublisher::from_registry().do_send(response);
             }
-            QueueJob::DownloadSong { requested_song } => {
-                self.download_song(ctx, requested_song);
+            QueueJob::DownloadSong {
+                requested_song,
+                requester,
+            } => {
+                // client that can't request any more songs doesn't have to wait for the download
+                if let Err(rejection) = self.check_requester(&requested_song.requested_by) {
+                    reject_request(
+                        &requester,
+                        requested_song.name,
+                        requested_song.artists,
+                        rejection,
+                    );
+                    return;
+                }
+                self.download_song(ctx, requested_song, requester);
             }
-            QueueJob::ScheduleSong { scheduled_song } => {
+            QueueJob::ScheduleSong { mut scheduled_song } => {
+                scheduled_song.turn = self.turns.next(&scheduled_song.requested_by);
+                self.db.do_send(SaveQueuedSong {
+                    song: QueuedSong::from(&scheduled_song),
+                });
                 self.songs_queue.push(scheduled_song);
                 if self.active_song.is_none() {
                     self.next_song(ctx);
@@ -97,13 +445,100 @@ impl SongQueue {
                 // sort songs by the time they were requested at
                 self.sort_songs();
             }
-            QueueJob::SkipSong => {
-                self.radio.do_send(SkipSong {
-                    queue_addr: ctx.address(),
+            QueueJob::VoteSkip { voter } => {
+                let song_id = match self.active_song.as_ref() {
+                    Some(song) => song.id,
+                    None => return,
+                };
+                self.skip_votes.insert(voter);
+                let future = wrap_future::<_, Self>(
+                    ClientPublisher::from_registry().send(GetClientCount {}),
+                );
+                ctx.spawn(
+                    future
+                        .map(move |clients, actor, ctx| {
+                            actor.count_skip_votes(ctx, song_id, clients)
+                        })
+                        .map_err(|e, _actor, _ctx| println!("publisher crashed - {:#?}", e)),
+                );
+            }
+            QueueJob::WithdrawSkipVote { voter } => {
+                self.skip_votes.remove(&voter);
+            }
+            QueueJob::VoteSong { uuid, voter, vote } => {
+                let song = match self.songs_queue.iter_mut().find(|song| song.uuid == uuid) {
+                    Some(song) => song,
+                    None => return,
+                };
+                if vote == 0 {
+                    song.votes.remove(&voter);
+                } else {
+                    song.votes.insert(voter, vote.signum());
+                }
+                song.score = song.votes.values().sum();
+                let response = UserMessage::<SongScore> {
+                    success: true,
+                    action: "song_score".to_owned(),
+                    value: SongScore {
+                        uuid,
+                        score: song.score,
+                    },
+                };
+                ClientPublisher::from_registry().do_send(response);
+                self.reorder();
+            }
+            QueueJob::MoveSongInQueue { uuid, index } => {
+                let last_index = self.songs_queue.len().saturating_sub(1);
+                let song = match self.songs_queue.iter_mut().find(|song| song.uuid == uuid) {
+                    Some(song) => song,
+                    None => return,
+                };
+                song.pin = Some(Pin {
+                    index: index.min(last_index),
+                    moved_at: Utc::now(),
                 });
+                self.reorder();
+            }
+            QueueJob::EnqueuePlaylist {
+                playlist_id,
+                requested_by,
+            } => {
+                self.enqueue_playlist(ctx, playlist_id, requested_by);
+            }
+            QueueJob::PauseSong => {
+                let future = wrap_future::<_, Self>(self.radio.send(PauseSong {}));
+                ctx.spawn(
+                    future
+                        .map(|res, _actor, _ctx| {
+                            if let Ok(position) = res {
+                                broadcast_position("playback_paused", position);
+                            }
+                        })
+                        .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+                );
+            }
+            QueueJob::ResumeSong if !self.is_on_air() => {
+                // song is held until the radio goes on air again
+            }
+            QueueJob::ResumeSong => {
+                let future = wrap_future::<_, Self>(self.radio.send(ResumeSong {}));
+                ctx.spawn(
+                    future
+                        .map(|res, _actor, _ctx| {
+                            if let Ok(position) = res {
+                                broadcast_position("playback_resumed", position);
+                            }
+                        })
+                        .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+                );
             }
             QueueJob::DeleteSongFromQueue { uuid } => {
-                self.songs_queue.retain(|s| s.uuid != uuid);
+                if let Some(index) = self.songs_queue.iter().position(|s| s.uuid == uuid) {
+                    self.remove_song(index);
+                }
+                self.db.do_send(DeleteQueuedSong {
+                    uuid: uuid.to_string(),
+                });
 
                 // song is now deleted from queue, let's send its uuid, so e.g when there are
                 // few clients connected, they all will have updated queue
@@ -114,32 +549,447 @@ impl SongQueue {
                 };
                 ClientPublisher::from_registry().do_send(response);
             }
+            QueueJob::RemoveDeletedSong { song_id } => {
+                let uuids: Vec<Uuid> = self
+                    .songs_queue
+                    .iter()
+                    .filter(|scheduled_song| scheduled_song.song.id == song_id)
+                    .map(|scheduled_song| scheduled_song.uuid)
+                    .collect();
+                for uuid in uuids {
+                    self.handle_activities(ctx, QueueJob::DeleteSongFromQueue { uuid });
+                }
+                // song on air is played till the end, but it isn't played again in repeat-one mode
+                if self.active_song.as_ref().map(|song| song.id) == Some(song_id) {
+                    self.skipped = true;
+                }
+            }
         }
     }
 
-    /// Sorts songs by time they were requested at.
+    /// Loads queue saved before the server was restarted, song that was on air is played first.
+    /// Radio starts playing only after that, otherwise it would pick a random song.
+    fn load_queue(&mut self, ctx: &mut ActorContext) {
+        let future = wrap_future::<_, Self>(self.db.send(GetQueuedSongs {}));
+        ctx.spawn(
+            future
+                .map(|res, actor, ctx| {
+                    let mut active_song = None;
+                    match res {
+                        Ok(queued_songs) => {
+                            for (queued_song, song) in queued_songs {
+                                let uuid = match Uuid::parse_str(&queued_song.uuid) {
+                                    Ok(uuid) => uuid,
+                                    Err(_) => continue,
+                                };
+                                let scheduled_song = ScheduledSong {
+                                    song,
+                                    requested_at: DateTime::from_utc(queued_song.requested_at, Utc),
+                                    uuid,
+                                    requested_by: queued_song.requested_by,
+                                    score: 0,
+                                    votes: HashMap::new(),
+                                    pin: None,
+                                    turn: 0,
+                                };
+                                if queued_song.active {
+                                    active_song = Some(scheduled_song);
+                                } else {
+                                    actor.songs_queue.push(scheduled_song);
+                                }
+                            }
+                        }
+                        Err(e) => println!("couldn't load the queue - {}", e),
+                    }
+                    // turns aren't saved, so they're given again in the order songs were requested in
+                    actor.songs_queue.sort_by_key(|song| song.requested_at);
+                    for song in actor.songs_queue.iter_mut() {
+                        song.turn = actor.turns.next(&song.requested_by);
+                    }
+                    actor.sort_songs();
+                    if let Some(active_song) = active_song {
+                        actor.songs_queue.insert(0, active_song);
+                    }
+                    // first song is played once it's known that the radio is on air
+                    actor.check_schedule(ctx);
+                })
+                .map_err(|e, _actor, _ctx| println!("db crashed - {:#?}", e)),
+        );
+    }
+
+    fn is_on_air(&self) -> bool {
+        self.on_air == Some(true)
+    }
+
+    /// Checks whether the radio should be on air according to the schedule from the radio's config.
+    fn check_schedule(&mut self, ctx: &mut ActorContext) {
+        let future = wrap_future::<_, Self>(self.radio.send(GetConfig {}));
+        ctx.spawn(
+            future
+                .map(|config, actor, ctx| {
+                    let now = Local::now().naive_local();
+                    let air_time = AirTime {
+                        on_air: config.schedule.is_on_air(now),
+                        next_change: config.schedule.next_change(now),
+                    };
+                    actor.set_on_air(ctx, air_time);
+                })
+                .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+        );
+    }
+
+    /// Stops the radio when it goes off air, the song on air is paused and held along with the queue.
+    /// Once it goes on air again, the held song is resumed or the next one is played.
+    fn set_on_air(&mut self, ctx: &mut ActorContext, air_time: AirTime) {
+        let was_on_air = self.on_air.replace(air_time.on_air);
+        if was_on_air == Some(air_time.on_air) {
+            return;
+        }
+        let action = if air_time.on_air { "on_air" } else { "off_air" };
+        if air_time.on_air {
+            let future = wrap_future::<_, Self>(self.radio.send(ResumeSong {}));
+            ctx.spawn(
+                future
+                    .map(|res, actor, ctx| match res {
+                        Ok(position) => broadcast_position("playback_resumed", position),
+                        // there was no song held
+                        Err(_) => actor.next_song(ctx),
+                    })
+                    .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+            );
+        } else {
+            self.radio.do_send(PauseSong {});
+        }
+        let response = UserMessage::<AirTime> {
+            success: true,
+            action: action.to_owned(),
+            value: air_time,
+        };
+        ClientPublisher::from_registry().do_send(response);
+    }
+
+    /// Broadcasts position of the active song unless it's paused.
+    fn broadcast_progress(&mut self, ctx: &mut ActorContext) {
+        let future = wrap_future::<_, Self>(self.radio.send(GetPlaybackPosition {}));
+        ctx.spawn(
+            future
+                .map(|position, _actor, _ctx| {
+                    if let Some(position) = position.filter(|position| !position.paused) {
+                        broadcast_position("playback_progress", position);
+                    }
+                })
+                .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+        );
+    }
+
+    /// Sorts songs in the order they should be played in.
+    /// Songs with higher score go first, order decides only between songs with the same score.
+    /// Songs moved by hand are put back at their positions afterwards.
     fn sort_songs(&mut self) {
-        self.songs_queue
-            .sort_by(|a, b| a.requested_at.time().cmp(&b.requested_at.time()));
+        let (mut pinned, mut songs): (Vec<ScheduledSong>, Vec<ScheduledSong>) = self
+            .songs_queue
+            .drain(..)
+            .partition(|song| song.pin.is_some());
+        self.order.sort(&mut songs);
+        songs.sort_by_key(|song| -song.score);
+        // inserting from the lowest position keeps positions of the songs inserted before,
+        // song inserted later at the same position pushes the earlier one back
+        pinned.sort_by_key(|song| song.pin.as_ref().map(|pin| (pin.index, pin.moved_at)));
+        for song in pinned {
+            let index = song
+                .pin
+                .as_ref()
+                .map_or(0, |pin| pin.index)
+                .min(songs.len());
+            songs.insert(index, song);
+        }
+        self.songs_queue = songs;
+    }
+
+    /// Sorts songs and lets all of the clients know which of them moved.
+    fn reorder(&mut self) {
+        let previous: HashMap<Uuid, usize> = self
+            .songs_queue
+            .iter()
+            .enumerate()
+            .map(|(index, song)| (song.uuid, index))
+            .collect();
+        self.sort_songs();
+        let positions: Vec<SongPosition> = self
+            .songs_queue
+            .iter()
+            .enumerate()
+            .filter(|(index, song)| previous.get(&song.uuid) != Some(index))
+            .map(|(index, song)| SongPosition {
+                uuid: song.uuid,
+                index,
+            })
+            .collect();
+        if positions.is_empty() {
+            return;
+        }
+        let response = UserMessage::<QueueDiff> {
+            success: true,
+            action: "queue_reordered".to_owned(),
+            value: QueueDiff { positions },
+        };
+        ClientPublisher::from_registry().do_send(response);
+    }
+
+    /// Removes song at given index, songs moved by hand behind it move forward along with the rest.
+    fn remove_song(&mut self, index: usize) -> ScheduledSong {
+        for pin in self
+            .songs_queue
+            .iter_mut()
+            .filter_map(|song| song.pin.as_mut())
+        {
+            if pin.index > index {
+                pin.index -= 1;
+            }
+        }
+        self.songs_queue.remove(index)
+    }
+
+    /// Skips the song on air if enough of the connected clients voted for it,
+    /// lets all of the clients know how the voting goes otherwise.
+    fn count_skip_votes(&mut self, ctx: &mut ActorContext, song_id: i32, clients: usize) {
+        // song could have changed while clients were counted
+        if self.active_song.as_ref().map(|song| song.id) != Some(song_id) {
+            return;
+        }
+        let needed = ((clients as f32 * self.skip_threshold).ceil() as usize).max(1);
+        let votes = self.skip_votes.len();
+        let response = UserMessage::<SkipVotes> {
+            success: true,
+            action: "skip_votes".to_owned(),
+            value: SkipVotes {
+                song_id,
+                votes,
+                needed,
+            },
+        };
+        ClientPublisher::from_registry().do_send(response);
+        if votes >= needed {
+            self.skip_votes.clear();
+            self.skip_song(ctx);
+        }
+    }
+
+    /// Cuts off the song on air, radio asks for the next one afterwards.
+    fn skip_song(&mut self, ctx: &mut ActorContext) {
+        self.skipped = true;
+        self.db.do_send(FinishPlayedSong {
+            end: SongEnd::Skipped,
+        });
+        self.radio.do_send(SkipSong {
+            queue_addr: ctx.address(),
+        });
     }
-    /// Takes next song from the queue and plays it.
-    /// If queue is empty then it chooses random song from the database with nsfw marked to false.
+
+    /// Cuts off the song on air for the timed entry whose songs were pinned at `entry_moved_at`.
+    /// Song that was already handed over to the radio to be played next goes back to the queue right after them.
+    fn interrupt_song(&mut self, ctx: &mut ActorContext, entry_moved_at: DateTime<Utc>) {
+        self.skipped = true;
+        self.db.do_send(FinishPlayedSong {
+            end: SongEnd::Interrupted,
+        });
+        let future = wrap_future::<_, Self>(self.radio.send(InterruptSong {
+            queue_addr: ctx.address(),
+        }));
+        ctx.spawn(
+            future
+                .map(move |upcoming, actor, ctx| {
+                    if let Some(song) = upcoming {
+                        // first of the entry's songs may already be on air
+                        let entry_songs = actor
+                            .songs_queue
+                            .iter()
+                            .filter(|scheduled_song| {
+                                scheduled_song.pin.as_ref().map(|pin| pin.moved_at)
+                                    == Some(entry_moved_at)
+                            })
+                            .count();
+                        let moved_at = Utc::now();
+                        let mut scheduled_song = ScheduledSong::new(song, moved_at, None);
+                        scheduled_song.pin = Some(Pin {
+                            index: entry_songs,
+                            moved_at,
+                        });
+                        scheduled_song.turn = actor.turns.round;
+                        actor.db.do_send(SaveQueuedSong {
+                            song: QueuedSong::from(&scheduled_song),
+                        });
+                        actor.songs_queue.push(scheduled_song);
+                        actor.sort_songs();
+                        ctx.notify(BroadcastState {});
+                    }
+                })
+                .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+        );
+    }
+
+    /// Plays timed entries whose time has come, the recurring ones are moved to their next occurrence.
+    /// Entries that are due while the radio is off air or were missed by a lot aren't played.
+    fn check_timed_entries(&mut self, ctx: &mut ActorContext) {
+        let now = Local::now().naive_local();
+        if !self.timed_entries.iter().any(|entry| entry.at <= now) {
+            return;
+        }
+        let (due, mut waiting): (Vec<TimedEntry>, Vec<TimedEntry>) = self
+            .timed_entries
+            .drain(..)
+            .partition(|entry| entry.at <= now);
+        for entry in due {
+            match entry.next_occurrence(now) {
+                Some(at) => {
+                    self.db.do_send(SetTimedEntryTime { id: entry.id, at });
+                    waiting.push(TimedEntry {
+                        at,
+                        ..entry.clone()
+                    });
+                }
+                None => self.db.do_send(DeleteTimedEntry { id: entry.id }),
+            }
+            if !self.is_on_air()
+                || now - entry.at > chrono::Duration::seconds(MISSED_TIMED_ENTRY_SECS)
+            {
+                println!("timed entry {} was missed", entry.id);
+                continue;
+            }
+            self.start_timed_entry(ctx, entry);
+        }
+        waiting.sort_by_key(|entry| entry.at);
+        self.timed_entries = waiting;
+    }
+
+    /// Puts songs of the timed entry at the front of the queue, they keep the playlist's order.
+    /// Song on air is cut off if the entry interrupts it, otherwise the entry is played right after it.
+    /// Entries are set up by the owner of the radio, so they aren't subject to the request limits.
+    fn start_timed_entry(&mut self, ctx: &mut ActorContext, entry: TimedEntry) {
+        let future = wrap_future::<_, Self>(self.db.send(GetTimedSongs {
+            entry: entry.clone(),
+        }));
+        ctx.spawn(
+            future
+                .map(move |res, actor, ctx| {
+                    let songs = match res {
+                        Ok(songs) => songs,
+                        Err(e) => {
+                            println!("couldn't load songs of the timed entry - {}", e);
+                            return;
+                        }
+                    };
+                    if songs.is_empty() {
+                        return;
+                    }
+                    let moved_at = Utc::now();
+                    for (index, song) in songs.into_iter().enumerate() {
+                        let mut scheduled_song = ScheduledSong::new(song, moved_at, None);
+                        // songs are pinned, so neither votes nor the order move them back
+                        scheduled_song.pin = Some(Pin { index, moved_at });
+                        scheduled_song.turn = actor.turns.round;
+                        actor.db.do_send(SaveQueuedSong {
+                            song: QueuedSong::from(&scheduled_song),
+                        });
+                        actor.songs_queue.push(scheduled_song);
+                    }
+                    actor.sort_songs();
+                    if actor.active_song.is_none() {
+                        actor.next_song(ctx);
+                    } else if entry.interrupt {
+                        actor.interrupt_song(ctx, moved_at);
+                    }
+                    let response = UserMessage::<TimedEntry> {
+                        success: true,
+                        action: "timed_entry_started".to_owned(),
+                        value: entry,
+                    };
+                    ClientPublisher::from_registry().do_send(response);
+                    ctx.notify(BroadcastState {});
+                })
+                .map_err(|e, _actor, _ctx| println!("db crashed - {:#?}", e)),
+        );
+    }
+
+    /// Index of the song from the queue that is played next, None if the queue is empty.
+    /// Song moved to the front by hand goes first even in shuffle mode.
+    fn next_index(&self) -> Option<usize> {
+        let first = self.songs_queue.first()?;
+        if !self.play_mode.shuffle || first.pin.is_some() {
+            return Some(0);
+        }
+        Some(rand::thread_rng().gen_range(0, self.songs_queue.len()))
+    }
+
+    /// Takes next song from the queue and plays it, the song on air is played again in repeat-one mode.
+    /// If queue is empty then autoplay picks one of the songs from the database with nsfw marked to false.
+    /// Nothing is played while the radio is off air.
     fn next_song(&mut self, ctx: &mut ActorContext) {
-        if let Some(scheduled_song) = self.songs_queue.first() {
+        let skipped = std::mem::replace(&mut self.skipped, false);
+        // song that was on air is over, after restart it's the one that was on air before it
+        self.db.do_send(FinishPlayedSong {
+            end: SongEnd::Played,
+        });
+        if !self.is_on_air() {
+            self.active_song = None;
+            return;
+        }
+        let repeated_song = self
+            .active_song
+            .clone()
+            .filter(|_| self.play_mode.repeat == RepeatMode::One && !skipped);
+        if let Some(song) = repeated_song {
+            // votes were counted when the song was played for the first time
+            let source = self.active_source;
+            self.handle_activities(
+                ctx,
+                QueueJob::PlaySong {
+                    song,
+                    source,
+                    score: 0,
+                },
+            );
+        } else if let Some(index) = self.next_index() {
+            let scheduled_song = self.remove_song(index);
+            self.turns.start(scheduled_song.turn);
+            self.db.do_send(SetActiveQueuedSong {
+                uuid: Some(scheduled_song.uuid.to_string()),
+            });
             self.handle_activities(
                 ctx,
                 QueueJob::PlaySong {
                     song: scheduled_song.song.clone(),
+                    source: PlaySource::Requested,
+                    score: scheduled_song.score,
                 },
             );
-            self.songs_queue.remove(0);
+            if self.play_mode.repeat == RepeatMode::Queue {
+                let scheduled_song = ScheduledSong::new(
+                    scheduled_song.song,
+                    Utc::now(),
+                    scheduled_song.requested_by,
+                );
+                self.handle_activities(ctx, QueueJob::ScheduleSong { scheduled_song });
+                ctx.notify(BroadcastState {});
+            }
         } else {
-            let future = wrap_future::<_, Self>(self.db.send(GetRandomSong {}));
+            // song that was on air isn't in the queue anymore
+            self.db.do_send(SetActiveQueuedSong { uuid: None });
+            let future = wrap_future::<_, Self>(self.db.send(GetAutoplaySong {
+                config: self.autoplay.clone(),
+            }));
             ctx.spawn(
                 future
                     .map(move |res, actor, ctx| {
                         if let Ok(song) = res {
-                            actor.handle_activities(ctx, QueueJob::PlaySong { song });
+                            actor.handle_activities(
+                                ctx,
+                                QueueJob::PlaySong {
+                                    song,
+                                    source: PlaySource::Autoplay,
+                                    score: 0,
+                                },
+                            );
                         } else {
                             let response = UserMessage::<EmptyValue> {
                                 success: false,
@@ -160,13 +1010,9 @@ impl SongQueue {
         ctx: &mut ActorContext,
         song: &Song,
         requested_at: DateTime<Utc>,
+        requested_by: Option<String>,
     ) -> impl ActorFuture<Item = (), Error = MailboxError, Actor = SongQueue> {
-        // this is scheduled song's uuid
-        let scheduled_song = ScheduledSong {
-            song: song.clone(),
-            requested_at,
-            uuid: Uuid::new_v4(), // uuid to easily identify song e.g during deleting it from queue
-        };
+        let scheduled_song = ScheduledSong::new(song.clone(), requested_at, requested_by);
         self.handle_activities(
             ctx,
             QueueJob::ScheduleSong {
@@ -182,41 +1028,397 @@ impl SongQueue {
         wrap_future(ClientPublisher::from_registry().send(response))
     }
 
+    /// Adds all of the playlist's songs to the queue, they keep the playlist's order.
+    fn enqueue_playlist(
+        &mut self,
+        ctx: &mut ActorContext,
+        playlist_id: i32,
+        requested_by: Option<String>,
+    ) {
+        let since = self.no_repeat_since();
+        let playlist = self.db.send(GetPlaylist { id: playlist_id });
+        let played = self.db.send(GetPlayedSongIds { since });
+        ctx.spawn(
+            wrap_future::<_, Self>(playlist.join(played))
+                .map(move |(res, played), actor, ctx| match res {
+                    Ok(playlist) => {
+                        let played = played.unwrap_or_else(|e| {
+                            println!("couldn't load the history - {}", e);
+                            Vec::new()
+                        });
+                        let requested_at = Utc::now();
+                        let mut count = 0;
+                        let mut skipped = 0;
+                        for (index, song) in playlist.songs.into_iter().enumerate() {
+                            // songs of the playlist are limited just like the ones requested one by one
+                            if actor.check_request(&song, &requested_by, &played).is_err() {
+                                skipped += 1;
+                                continue;
+                            }
+                            // songs are sorted by the time they were requested at
+                            let requested_at =
+                                requested_at + chrono::Duration::milliseconds(index as i64);
+                            let scheduled_song =
+                                ScheduledSong::new(song, requested_at, requested_by.clone());
+                            actor.handle_activities(ctx, QueueJob::ScheduleSong { scheduled_song });
+                            count += 1;
+                        }
+                        let response = UserMessage::<PlaylistEnqueued> {
+                            success: true,
+                            action: "playlist_enqueued".to_owned(),
+                            value: PlaylistEnqueued {
+                                playlist_id,
+                                songs: count,
+                                skipped,
+                            },
+                        };
+                        ClientPublisher::from_registry().do_send(response);
+                        ctx.notify(BroadcastState {});
+                    }
+                    Err(e) => {
+                        println!("couldn't enqueue the playlist - {}", e);
+                        let response = UserMessage::<EmptyValue> {
+                            success: false,
+                            action: "playlist_not_found".to_owned(),
+                            value: EmptyValue {},
+                        };
+                        ClientPublisher::from_registry().do_send(response);
+                    }
+                })
+                .map_err(|e, _actor, _ctx| println!("db crashed - {:#?}", e)),
+        );
+    }
+
     /// Downloads song from youtube and saves it in the database.
     fn get_song(
         &mut self,
         ctx: &mut ActorContext,
         requested_song: SongRequest,
-    ) -> impl ActorFuture<Item = Song, Error = MailboxError, Actor = SongQueue> {
-        wrap_future::<_, Self>(self.IO.send(DownloadSong { requested_song }))
-            .and_then(|song, actor, ctx| {
-                wrap_future(actor.db.send(SaveSong {
-                    song: song.unwrap(),
-                }))
+        requester: Addr<MyWebSocket>,
+    ) -> impl ActorFuture<Item = Option<Song>, Error = MailboxError, Actor = SongQueue> {
+        // with round robin order song of the client with fewer songs in the queue is played sooner, so it's downloaded first
+        let queued = match self.order {
+            QueueOrder::Fifo => None,
+            QueueOrder::RoundRobin => Some(
+                self.songs_queue
+                    .iter()
+                    .filter(|song| song.requested_by == requested_song.requested_by)
+                    .count(),
+            ),
+        };
+        // download is tracked from the moment it's requested, so it can be cancelled before it starts
+        let job = DownloadJob::new(&requested_song, queued);
+        let requested_by = requested_song.requested_by.clone();
+        *self.downloading.entry(requested_by.clone()).or_insert(0) += 1;
+        let job_id = job.id;
+        let (start, started) = oneshot::channel();
+        Downloads::from_registry().do_send(AddDownload { job, start });
+        let name = requested_song.name.clone();
+        let artists = requested_song.artists.clone();
+        let url = requested_song.url.clone();
+        // song is handed to the IO workers once it's its turn, it's never started if it's cancelled before that
+        wrap_future::<_, Self>(started.then(|started| Ok(started.is_ok())))
+            .and_then(move |started, actor, _ctx| {
+                let future: Box<
+                    dyn ActorFuture<
+                        Item = Result<NewSong, DownloadError>,
+                        Error = MailboxError,
+                        Actor = SongQueue,
+                    >,
+                > = if started {
+                    Box::new(wrap_future(actor.IO.send(DownloadSong {
+                        requested_song,
+                        job_id,
+                    })))
+                } else {
+                    Box::new(fut_ok(Err(DownloadError::Cancelled)).into_actor(actor))
+                };
+                future
+            })
+            .and_then(move |song, actor, ctx| {
+                if let Some(downloading) = actor.downloading.get_mut(&requested_by) {
+                    *downloading -= 1;
+                    if *downloading == 0 {
+                        actor.downloading.remove(&requested_by);
+                    }
+                }
+                let future: Box<
+                    dyn ActorFuture<Item = Option<Song>, Error = MailboxError, Actor = SongQueue>,
+                > = match song {
+                    Ok(song) => Box::new(wrap_future(actor.db.send(SaveSong { song })).map(
+                        |song, _a, _c| {
+                            song.map_err(|e| println!("couldn't save the song - {}", e))
+                                .ok()
+                        },
+                    )),
+                    // clients were already told about the cancelled download
+                    Err(DownloadError::Cancelled) => Box::new(fut_ok(None).into_actor(actor)),
+                    Err(error) => {
+                        let response = UserMessage::<DownloadFailed> {
+                            success: false,
+                            action: "song_download_failed".to_owned(),
+                            value: DownloadFailed {
+                                name,
+                                artists,
+                                url,
+                                reason: error.reason(),
+                                message: error.to_string(),
+                            },
+                        };
+                        requester.do_send(response);
+                        Box::new(fut_ok(None).into_actor(actor))
+                    }
+                };
+                future
             })
-            .and_then(|song, actor, ctx| (fut_ok(song.unwrap()).into_actor(actor)))
     }
     /// Downloads song from youtube via youtube-dl, but before any download starts, it firstly checks whether song exists in the db or not.
-    fn download_song(&mut self, ctx: &mut ActorContext, requested_song: SongRequest) {
+    fn download_song(
+        &mut self,
+        ctx: &mut ActorContext,
+        requested_song: SongRequest,
+        requester: Addr<MyWebSocket>,
+    ) {
         let requested_at = requested_song.requested_at;
-        ctx.spawn(
-            wrap_future::<_, Self>(self.db.send(CheckSongExistence {
+        let requested_by = requested_song.requested_by.clone();
+        let download_requester = requester.clone();
+        // song requested by its url is looked up by it, because its name may not be known yet
+        let existing_song = match &requested_song.url {
+            Some(url) => Either::A(self.db.send(GetSongBySourceUrl { url: url.clone() })),
+            None => Either::B(self.db.send(CheckSongExistence {
                 song_name: requested_song.name.clone(),
                 artists: requested_song.artists.clone(),
-            }))
-            .map(|song, actor, ctx| {
-                if let Ok(song) = song {
+            })),
+        };
+        ctx.spawn(
+            wrap_future::<_, Self>(existing_song)
+                .map(|song, actor, ctx| {
+                    if let Ok(song) = song {
+                        let future: Box<
+                            dyn ActorFuture<
+                                Item = Option<Song>,
+                                Error = MailboxError,
+                                Actor = SongQueue,
+                            >,
+                        > = Box::new(fut_ok(Some(song)).into_actor(actor));
+                        future
+                    } else {
+                        Box::new(actor.get_song(ctx, requested_song, download_requester))
+                    }
+                })
+                .and_then(|res, actor, ctx| res.map(|song, a, c| song))
+                .and_then(move |song, actor, _ctx| {
                     let future: Box<
-                        dyn ActorFuture<Item = Song, Error = MailboxError, Actor = SongQueue>,
-                    > = Box::new(fut_ok(song).into_actor(actor));
+                        dyn ActorFuture<Item = (), Error = MailboxError, Actor = SongQueue>,
+                    > = match song {
+                        Some(song) => {
+                            Box::new(actor.accept_song(song, requested_at, requested_by, requester))
+                        }
+                        None => Box::new(fut_ok(()).into_actor(actor)),
+                    };
                     future
-                } else {
-                    Box::new(actor.get_song(ctx, requested_song))
-                }
-            })
-            .and_then(|res, actor, ctx| res.map(|song, a, c| song))
-            .and_then(move |song, actor, ctx| actor.schedule_song(ctx, &song, requested_at))
-            .map_err(|e, a, c| println!("db crashed - {:#?}", e)),
+                })
+                .map_err(|e, a, c| println!("db crashed - {:#?}", e)),
+        );
+    }
+
+    /// Adds requested song to the queue unless it breaks the request limits, the requester is told why otherwise.
+    fn accept_song(
+        &mut self,
+        song: Song,
+        requested_at: DateTime<Utc>,
+        requested_by: Option<String>,
+        requester: Addr<MyWebSocket>,
+    ) -> impl ActorFuture<Item = (), Error = MailboxError, Actor = SongQueue> {
+        let since = self.no_repeat_since();
+        wrap_future::<_, Self>(self.db.send(GetPlayedSongIds { since })).and_then(
+            move |played, actor, ctx| {
+                let played = played.unwrap_or_else(|e| {
+                    println!("couldn't load the history - {}", e);
+                    Vec::new()
+                });
+                let future: Box<
+                    dyn ActorFuture<Item = (), Error = MailboxError, Actor = SongQueue>,
+                > = match actor.check_request(&song, &requested_by, &played) {
+                    Ok(()) => Box::new(actor.schedule_song(ctx, &song, requested_at, requested_by)),
+                    Err(rejection) => {
+                        reject_request(&requester, song.name, song.artists, rejection);
+                        Box::new(fut_ok(()).into_actor(actor))
+                    }
+                };
+                future
+            },
+        )
+    }
+
+    /// Songs that were on air since then can't be requested again.
+    fn no_repeat_since(&self) -> NaiveDateTime {
+        let minutes = self.request_limits.no_repeat_minutes.unwrap_or(0);
+        Utc::now().naive_utc() - chrono::Duration::minutes(i64::from(minutes))
+    }
+
+    /// Reason why the client can't request another song, it's checked before the song is downloaded.
+    /// Songs of the client that are still being downloaded count as queued.
+    fn check_requester(&self, requested_by: &Option<String>) -> Result<(), RequestRejection> {
+        if let Some(limit) = self.request_limits.max_songs_per_client {
+            let queued = self
+                .songs_queue
+                .iter()
+                .filter(|scheduled_song| scheduled_song.requested_by == *requested_by)
+                .count()
+                + self.downloading.get(requested_by).cloned().unwrap_or(0);
+            if queued >= limit {
+                return Err(RequestRejection::TooManySongs { limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reason why the song can't be added to the queue.
+    /// `played` are ids of the songs that were on air in the last `no_repeat_minutes`.
+    fn check_request(
+        &self,
+        song: &Song,
+        requested_by: &Option<String>,
+        played: &[i32],
+    ) -> Result<(), RequestRejection> {
+        self.check_requester(requested_by)?;
+        if let Some(limit) = self.request_limits.max_queue_duration {
+            let duration: i64 = self
+                .songs_queue
+                .iter()
+                .map(|scheduled_song| i64::from(scheduled_song.song.duration))
+                .sum();
+            if duration + i64::from(song.duration) > i64::from(limit) {
+                return Err(RequestRejection::QueueTooLong { limit });
+            }
+        }
+        let queued = self
+            .active_song
+            .iter()
+            .chain(
+                self.songs_queue
+                    .iter()
+                    .map(|scheduled_song| &scheduled_song.song),
+            )
+            .any(|queued_song| queued_song.id == song.id);
+        if queued {
+            return Err(RequestRejection::AlreadyQueued);
+        }
+        if let Some(minutes) = self.request_limits.no_repeat_minutes {
+            if played.contains(&song.id) {
+                return Err(RequestRejection::RecentlyPlayed { minutes });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lets the client know why its song wasn't added to the queue.
+fn reject_request(
+    requester: &Addr<MyWebSocket>,
+    name: String,
+    artists: String,
+    rejection: RequestRejection,
+) {
+    let response = UserMessage::<RequestRejected> {
+        success: false,
+        action: "song_request_rejected".to_owned(),
+        value: RequestRejected {
+            name,
+            artists,
+            rejection,
+        },
+    };
+    requester.do_send(response);
+}
+
+/// Lets all of the clients know where the radio is in the active song.
+fn broadcast_position(action: &str, position: PlaybackPosition) {
+    let response = UserMessage::<PlaybackPosition> {
+        success: true,
+        action: action.to_owned(),
+        value: position,
+    };
+    ClientPublisher::from_registry().do_send(response);
+}
+
+/// Change queue's part of the config.
+#[derive(Message)]
+pub struct SetQueueConfig {
+    pub order: QueueOrder,
+    pub skip_threshold: f32,
+    pub autoplay: AutoplayConfig,
+    pub request_limits: RequestLimits,
+}
+
+/// Sorts the queue again and lets all of the clients know which songs moved.
+impl Handler<SetQueueConfig> for SongQueue {
+    type Result = ();
+    fn handle(&mut self, msg: SetQueueConfig, _ctx: &mut Self::Context) -> Self::Result {
+        self.skip_threshold = msg.skip_threshold;
+        self.autoplay = msg.autoplay;
+        self.request_limits = msg.request_limits;
+        self.order = msg.order;
+        self.reorder();
+    }
+}
+
+/// Change how songs are taken from the queue, returns the play mode after the change.
+pub struct SetPlayMode {
+    pub update: PlayModeUpdate,
+}
+
+impl Message for SetPlayMode {
+    type Result = PlayMode;
+}
+
+/// Lets all of the clients know about the new play mode.
+impl Handler<SetPlayMode> for SongQueue {
+    type Result = MessageResult<SetPlayMode>;
+    fn handle(&mut self, msg: SetPlayMode, _ctx: &mut Self::Context) -> Self::Result {
+        self.play_mode = PlayMode {
+            repeat: msg.update.repeat.unwrap_or(self.play_mode.repeat),
+            shuffle: msg.update.shuffle.unwrap_or(self.play_mode.shuffle),
+        };
+        let response = UserMessage::<PlayMode> {
+            success: true,
+            action: "play_mode_changed".to_owned(),
+            value: self.play_mode,
+        };
+        ClientPublisher::from_registry().do_send(response);
+        MessageResult(self.play_mode)
+    }
+}
+
+pub struct GetPlayMode;
+
+impl Message for GetPlayMode {
+    type Result = PlayMode;
+}
+
+impl Handler<GetPlayMode> for SongQueue {
+    type Result = MessageResult<GetPlayMode>;
+    fn handle(&mut self, _msg: GetPlayMode, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.play_mode)
+    }
+}
+
+/// Load timed entries again after they were changed.
+#[derive(Message)]
+pub struct ReloadTimedEntries;
+
+impl Handler<ReloadTimedEntries> for SongQueue {
+    type Result = ();
+    fn handle(&mut self, _msg: ReloadTimedEntries, ctx: &mut Self::Context) -> Self::Result {
+        let future = wrap_future::<_, Self>(self.db.send(GetTimedEntries {}));
+        ctx.spawn(
+            future
+                .map(|res, actor, _ctx| match res {
+                    Ok(entries) => actor.timed_entries = entries,
+                    Err(e) => println!("couldn't load timed entries - {}", e),
+                })
+                .map_err(|e, _actor, _ctx| println!("db crashed - {:#?}", e)),
         );
     }
 }
@@ -236,20 +1438,183 @@ pub struct BroadcastState;
 pub struct QueueState {
     pub active_song: Option<Song>,
     pub songs_queue: Vec<ScheduledSong>,
+    // position of the active song, so clients that just connected can show it
+    pub position: Option<PlaybackPosition>,
+    // lets clients know whether songs actually go on air
+    pub radio: RadioStatus,
+    // whether the radio is on air according to the schedule
+    pub on_air: bool,
+    // songs that were on air recently, the most recent ones go first
+    pub history: Vec<HistoryEntry>,
+    pub play_mode: PlayMode,
 }
 
 // Broadcasts queue state after receiving message from websocket that there's new connection available.
 impl Handler<BroadcastState> for SongQueue {
     type Result = ();
     fn handle(&mut self, msg: BroadcastState, ctx: &mut Self::Context) -> Self::Result {
-        let response = UserMessage::<QueueState> {
-            success: true,
-            action: "queue_state".to_owned(),
-            value: QueueState {
-                active_song: self.active_song.clone(),
-                songs_queue: self.songs_queue.clone(),
-            },
-        };
-        ClientPublisher::from_registry().do_send(response);
+        let future = self.radio.send(GetPlaybackPosition {}).join3(
+            self.radio.send(GetRadioStatus {}),
+            self.db.send(GetHistory {
+                offset: 0,
+                limit: RECENT_HISTORY,
+            }),
+        );
+        ctx.spawn(
+            wrap_future::<_, Self>(future)
+                .map(|(position, radio, history), actor, _ctx| {
+                    let history = history
+                        .map(|(entries, _total)| entries)
+                        .unwrap_or_else(|e| {
+                            println!("couldn't load the history - {}", e);
+                            Vec::new()
+                        });
+                    let response = UserMessage::<QueueState> {
+                        success: true,
+                        action: "queue_state".to_owned(),
+                        value: QueueState {
+                            active_song: actor.active_song.clone(),
+                            songs_queue: actor.songs_queue.clone(),
+                            position,
+                            radio,
+                            on_air: actor.is_on_air(),
+                            history,
+                            play_mode: actor.play_mode,
+                        },
+                    };
+                    ClientPublisher::from_registry().do_send(response);
+                })
+                .map_err(|e, _actor, _ctx| println!("radio crashed - {:#?}", e)),
+        );
+    }
+}
+
+// API functions
+/// GET /queue/mode
+pub fn get_play_mode(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .queue_handler
+        .send(GetPlayMode {})
+        .and_then(|play_mode| Ok(HttpResponse::Ok().json(play_mode)))
+        .from_err()
+        .responder()
+}
+
+/// PUT /queue/mode
+/// Changes are broadcasted to all of the clients.
+pub fn update_play_mode(
+    update: Json<PlayModeUpdate>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    state
+        .queue_handler
+        .send(SetPlayMode {
+            update: update.into_inner(),
+        })
+        .and_then(|play_mode| Ok(HttpResponse::Ok().json(play_mode)))
+        .from_err()
+        .responder()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::song::test_helpers::song;
+
+    /// Song scheduled by given client at given second, the way the queue schedules it.
+    fn schedule(turns: &mut Turns, id: i32, client: &str, second: i64) -> ScheduledSong {
+        let mut scheduled_song = ScheduledSong::new(
+            song(id, "artist"),
+            Utc.timestamp(second, 0),
+            Some(client.to_owned()),
+        );
+        scheduled_song.turn = turns.next(&scheduled_song.requested_by);
+        scheduled_song
+    }
+
+    fn ids(songs: &[ScheduledSong]) -> Vec<i32> {
+        songs.iter().map(|song| song.song.id).collect()
+    }
+
+    /// Takes the first song from the queue, the way the queue plays it.
+    fn play_first(turns: &mut Turns, songs: &mut Vec<ScheduledSong>) {
+        let song = songs.remove(0);
+        turns.start(song.turn);
+        QueueOrder::RoundRobin.sort(songs);
+    }
+
+    #[test]
+    fn fifo_plays_songs_in_order_they_were_requested_in() {
+        let mut turns = Turns::default();
+        let mut songs = vec![
+            schedule(&mut turns, 1, "a", 0),
+            schedule(&mut turns, 3, "a", 2),
+            schedule(&mut turns, 2, "b", 1),
+        ];
+        QueueOrder::Fifo.sort(&mut songs);
+        assert_eq!(ids(&songs), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn round_robin_keeps_turns_after_songs_are_played() {
+        let mut turns = Turns::default();
+        let mut songs = vec![
+            schedule(&mut turns, 1, "a", 0),
+            schedule(&mut turns, 2, "a", 1),
+            schedule(&mut turns, 3, "a", 2),
+            schedule(&mut turns, 4, "b", 3),
+        ];
+        QueueOrder::RoundRobin.sort(&mut songs);
+        assert_eq!(ids(&songs), vec![1, 4, 2, 3]);
+        // a's second song doesn't get the first turn once the first one is played
+        play_first(&mut turns, &mut songs);
+        assert_eq!(ids(&songs), vec![4, 2, 3]);
+        play_first(&mut turns, &mut songs);
+        assert_eq!(ids(&songs), vec![2, 3]);
+    }
+
+    #[test]
+    fn round_robin_lets_new_clients_join_the_current_turn() {
+        let mut turns = Turns::default();
+        let mut songs = vec![
+            schedule(&mut turns, 1, "a", 0),
+            schedule(&mut turns, 2, "a", 1),
+            schedule(&mut turns, 3, "a", 2),
+            schedule(&mut turns, 4, "b", 3),
+        ];
+        QueueOrder::RoundRobin.sort(&mut songs);
+        play_first(&mut turns, &mut songs);
+        play_first(&mut turns, &mut songs);
+        // c didn't request anything yet, b had its turn and a has songs in the next two turns
+        songs.push(schedule(&mut turns, 5, "c", 4));
+        songs.push(schedule(&mut turns, 6, "b", 5));
+        songs.push(schedule(&mut turns, 7, "a", 6));
+        QueueOrder::RoundRobin.sort(&mut songs);
+        assert_eq!(ids(&songs), vec![5, 2, 6, 3, 7]);
+    }
+
+    #[test]
+    fn round_robin_doesnt_give_back_turns_that_are_over() {
+        let mut turns = Turns::default();
+        let mut songs = vec![
+            schedule(&mut turns, 1, "a", 0),
+            schedule(&mut turns, 2, "b", 1),
+            schedule(&mut turns, 3, "b", 2),
+            schedule(&mut turns, 4, "b", 3),
+        ];
+        QueueOrder::RoundRobin.sort(&mut songs);
+        play_first(&mut turns, &mut songs);
+        play_first(&mut turns, &mut songs);
+        play_first(&mut turns, &mut songs);
+        songs.push(schedule(&mut turns, 5, "c", 4));
+        songs.push(schedule(&mut turns, 6, "c", 5));
+        QueueOrder::RoundRobin.sort(&mut songs);
+        assert_eq!(ids(&songs), vec![5, 4, 6]);
+        play_first(&mut turns, &mut songs);
+        play_first(&mut turns, &mut songs);
+        // a's next turn would be the second one, which is already over, so it waits for c's song of the third one
+        songs.push(schedule(&mut turns, 7, "a", 6));
+        QueueOrder::RoundRobin.sort(&mut songs);
+        assert_eq!(ids(&songs), vec![6, 7]);
     }
 }
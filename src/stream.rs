@@ -0,0 +1,442 @@
+use crate::audio::{self, CHANNELS, SAMPLE_RATE};
+use crate::radio::Radio;
+use crate::transmitter::{describe_exit, STDERR_LIMIT};
+use actix::{Addr, Message};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of frames mixed and written at once, 0.1s of audio.
+const CHUNK_FRAMES: usize = (SAMPLE_RATE / 10) as usize;
+/// How much audio can be written ahead of what's actually on air.
+const MAX_AHEAD: Duration = Duration::from_secs(1);
+/// How many seconds before the crossfade starts the next song is requested,
+/// so the queue has time to hand it over.
+const PREPARE_AHEAD: u64 = 1;
+/// How long it's waited for the rest of stderr of the transmitter that exited.
+const STDERR_GRACE: Duration = Duration::from_millis(50);
+
+/// Continuous audio stream fed into a single transmitter process.
+/// Songs are decoded and mixed by a separate thread, so the transmitter never
+/// goes off air between songs.
+pub struct Stream {
+    transmitter: Child,
+    commands: Sender<StreamCommand>,
+    stop: Arc<AtomicBool>,
+    // end of what the transmitter wrote to stderr
+    stderr: Arc<Mutex<Vec<u8>>>,
+}
+
+pub struct StreamOptions {
+    /// Seconds during which the ending song is mixed with the next one.
+    pub crossfade: i32,
+    /// If false songs aren't decoded at all and silence is streamed instead.
+    pub needs_audio: bool,
+    /// Named pipe used to update radio text when next song starts.
+    pub control_pipe: Option<String>,
+}
+
+/// Song that should be streamed.
+pub struct StreamSong {
+    pub song_id: i32,
+    pub path: String,
+    /// Second of the song the stream starts at.
+    pub offset: i32,
+    pub duration: i32,
+    /// Gain in dB applied to the song.
+    pub gain: f32,
+    pub radio_text: String,
+}
+
+enum StreamCommand {
+    Play(StreamSong),
+    Skip,
+    Clear,
+}
+
+/// Events sent from the stream to the radio.
+pub enum StreamEvent {
+    /// Song handed over to the stream started playing.
+    Started,
+    /// Song on air is about to end, so the next one should be handed over.
+    NeedNext,
+    /// Song on air ended and there was no next song to play.
+    Finished,
+    /// Decoder of the song on air stopped with an error, so the song ended early.
+    DecoderFailed { song_id: i32, error: String },
+    /// Stream can't go on, e.g because the transmitter died.
+    Failed(String),
+}
+
+impl Message for StreamEvent {
+    type Result = ();
+}
+
+impl Stream {
+    /// Spawns the transmitter and starts the thread feeding it.
+    pub fn start(
+        mut command: Command,
+        radio: Addr<Radio>,
+        options: StreamOptions,
+    ) -> io::Result<Stream> {
+        let mut transmitter = command
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let output = transmitter
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other("transmitter has no stdin"))?;
+        let stderr = Arc::new(Mutex::new(Vec::new()));
+        if let Some(transmitter_stderr) = transmitter.stderr.take() {
+            let stderr = stderr.clone();
+            thread::spawn(move || collect_stderr(transmitter_stderr, &stderr));
+        }
+        let (commands, receiver) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let feeder = Feeder {
+            output,
+            radio,
+            commands: receiver,
+            stop: stop.clone(),
+            options,
+        };
+        thread::spawn(move || feeder.run());
+        Ok(Stream {
+            transmitter,
+            commands,
+            stop,
+            stderr,
+        })
+    }
+
+    /// Plays the song right away if nothing is on air,
+    /// otherwise the song is played after the one on air.
+    pub fn play(&self, song: StreamSong) {
+        let _ = self.commands.send(StreamCommand::Play(song));
+    }
+
+    /// Drops the song on air and moves on to the next one, if there's any.
+    pub fn skip(&self) {
+        let _ = self.commands.send(StreamCommand::Skip);
+    }
+
+    /// Drops the song on air along with the one handed over to be played after it.
+    pub fn clear(&self) {
+        let _ = self.commands.send(StreamCommand::Clear);
+    }
+
+    /// Kills the transmitter, feeding thread exits on its own right after.
+    /// Returns how the transmitter stopped if it stopped on its own before it was killed.
+    pub fn stop(mut self) -> Option<String> {
+        self.stop.store(true, Ordering::SeqCst);
+        let exit = match self.transmitter.try_wait() {
+            Ok(Some(status)) => {
+                // give the thread a moment to read what transmitter wrote right before exiting
+                thread::sleep(STDERR_GRACE);
+                let stderr = self
+                    .stderr
+                    .lock()
+                    .map(|stderr| stderr.clone())
+                    .unwrap_or_default();
+                Some(describe_exit(status, &stderr))
+            }
+            _ => None,
+        };
+        let _ = self.transmitter.kill();
+        let _ = self.transmitter.wait();
+        exit
+    }
+}
+
+/// Keeps the end of what transmitter writes to stderr, until it exits.
+fn collect_stderr(mut transmitter_stderr: impl Read, stderr: &Mutex<Vec<u8>>) {
+    let mut buffer = [0u8; 512];
+    while let Ok(read) = transmitter_stderr.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+        if let Ok(mut stderr) = stderr.lock() {
+            stderr.extend_from_slice(&buffer[..read]);
+            let excess = stderr.len().saturating_sub(STDERR_LIMIT);
+            stderr.drain(..excess);
+        }
+    }
+}
+
+/// Decoded song read by the feeder.
+struct Source {
+    song_id: i32,
+    // None if the song isn't decoded and silence is played instead
+    decoder: Option<Child>,
+    output: Option<ChildStdout>,
+    // estimated from song's duration, so it's known when to start the crossfade
+    frames_left: u64,
+    radio_text: String,
+}
+
+impl Source {
+    fn open(song: StreamSong, needs_audio: bool) -> io::Result<Source> {
+        let mut decoder = if needs_audio {
+            Some(audio::pcm_decoder(&song.path, song.offset, song.gain).spawn()?)
+        } else {
+            None
+        };
+        let output = decoder.as_mut().and_then(|decoder| decoder.stdout.take());
+        Ok(Source {
+            song_id: song.song_id,
+            decoder,
+            output,
+            frames_left: (song.duration - song.offset).max(0) as u64 * u64::from(SAMPLE_RATE),
+            radio_text: song.radio_text,
+        })
+    }
+
+    /// Fills the buffer with next samples, returns how many samples were read, 0 if song is over.
+    fn read(&mut self, buffer: &mut [i16]) -> io::Result<usize> {
+        let samples = match self.output.as_mut() {
+            Some(output) => read_samples(output, buffer)?,
+            None => {
+                let samples = buffer
+                    .len()
+                    .min(self.frames_left as usize * CHANNELS as usize);
+                buffer[..samples].iter_mut().for_each(|sample| *sample = 0);
+                samples
+            }
+        };
+        self.frames_left = self
+            .frames_left
+            .saturating_sub((samples / CHANNELS as usize) as u64);
+        Ok(samples)
+    }
+
+    /// Why the decoder stopped, None if it didn't fail. Called once the song is over, so it doesn't block.
+    fn failure(&mut self) -> Option<String> {
+        let status = self.decoder.as_mut()?.wait().ok()?;
+        if status.success() {
+            None
+        } else {
+            Some(format!("decoder stopped with {}", status))
+        }
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        if let Some(mut decoder) = self.decoder.take() {
+            let _ = decoder.kill();
+            let _ = decoder.wait();
+        }
+    }
+}
+
+/// Reads as many whole frames as fit into the buffer, returns number of samples read.
+fn read_samples(reader: &mut impl Read, buffer: &mut [i16]) -> io::Result<usize> {
+    let mut bytes = vec![0u8; buffer.len() * 2];
+    let mut filled = 0;
+    while filled < bytes.len() {
+        match reader.read(&mut bytes[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    let frame_size = 2 * CHANNELS as usize;
+    let samples = filled / frame_size * CHANNELS as usize;
+    for (sample, bytes) in buffer.iter_mut().zip(bytes.chunks(2)).take(samples) {
+        *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    Ok(samples)
+}
+
+/// Thread writing mixed songs into transmitter's stdin.
+struct Feeder {
+    output: std::process::ChildStdin,
+    radio: Addr<Radio>,
+    commands: Receiver<StreamCommand>,
+    stop: Arc<AtomicBool>,
+    options: StreamOptions,
+}
+
+impl Feeder {
+    fn run(self) {
+        let radio = self.radio.clone();
+        let stop = self.stop.clone();
+        if let Err(e) = self.feed() {
+            // stream was stopped on purpose, so the error is expected
+            if !stop.load(Ordering::SeqCst) {
+                radio.do_send(StreamEvent::Failed(e.to_string()));
+            }
+        }
+    }
+
+    fn feed(mut self) -> io::Result<()> {
+        let crossfade_frames = self.options.crossfade.max(0) as u64 * u64::from(SAMPLE_RATE);
+        let prepare_frames = crossfade_frames + PREPARE_AHEAD * u64::from(SAMPLE_RATE);
+        // opened for reading as well, so opening doesn't block until transmitter opens it
+        let mut control_pipe = self
+            .options
+            .control_pipe
+            .as_ref()
+            .and_then(|path| OpenOptions::new().read(true).write(true).open(path).ok());
+        let mut current: Option<Source> = None;
+        let mut next: Option<Source> = None;
+        // whether next song was requested for the current one
+        let mut next_requested = false;
+        // whether next song is already mixed with the current one
+        let mut mixing = false;
+        let mut buffer = vec![0i16; CHUNK_FRAMES * CHANNELS as usize];
+        let mut next_buffer = buffer.clone();
+        let started_at = Instant::now();
+        let mut frames_written: u64 = 0;
+
+        self.output.write_all(&audio::stream_wav_header())?;
+        while !self.stop.load(Ordering::SeqCst) {
+            loop {
+                match self.commands.try_recv() {
+                    Ok(StreamCommand::Play(song)) => {
+                        let source = Source::open(song, self.options.needs_audio)?;
+                        if current.is_none() {
+                            current = Some(self.start(source, &mut control_pipe));
+                            next_requested = false;
+                        } else {
+                            next = Some(source);
+                        }
+                    }
+                    Ok(StreamCommand::Skip) => {
+                        current = next.take().map(|source| {
+                            if mixing {
+                                source
+                            } else {
+                                self.start(source, &mut control_pipe)
+                            }
+                        });
+                        next_requested = false;
+                        mixing = false;
+                    }
+                    Ok(StreamCommand::Clear) => {
+                        current = None;
+                        next = None;
+                        next_requested = false;
+                        mixing = false;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            let samples = match current.as_mut() {
+                Some(source) => {
+                    let frames_left = source.frames_left;
+                    let samples = source.read(&mut buffer)?;
+                    if samples == 0 {
+                        // song that's already mixed with the next one is as good as over, even if its decoder failed
+                        if !mixing {
+                            if let Some(error) = source.failure() {
+                                self.radio.do_send(StreamEvent::DecoderFailed {
+                                    song_id: source.song_id,
+                                    error,
+                                });
+                            }
+                        }
+                        // song is over, next one is either already mixed in or it starts right now
+                        current = next.take().map(|source| {
+                            if mixing {
+                                source
+                            } else {
+                                self.start(source, &mut control_pipe)
+                            }
+                        });
+                        if current.is_none() {
+                            self.radio.do_send(StreamEvent::Finished);
+                        }
+                        next_requested = false;
+                        mixing = false;
+                        continue;
+                    }
+                    if !next_requested && source.frames_left <= prepare_frames {
+                        self.radio.do_send(StreamEvent::NeedNext);
+                        next_requested = true;
+                    }
+                    if let Some(next_source) = next.as_mut() {
+                        if crossfade_frames > 0 && source.frames_left <= crossfade_frames {
+                            if !mixing {
+                                mixing = true;
+                                self.radio.do_send(StreamEvent::Started);
+                                update_radio_text(&mut control_pipe, &next_source.radio_text);
+                            }
+                            let next_samples = next_source.read(&mut next_buffer[..samples])?;
+                            crossfade(
+                                &mut buffer[..samples],
+                                &next_buffer[..next_samples],
+                                frames_left,
+                                crossfade_frames,
+                            );
+                        }
+                    }
+                    samples
+                }
+                None => {
+                    // nothing to play, keep the transmitter on air with silence
+                    buffer.iter_mut().for_each(|sample| *sample = 0);
+                    buffer.len()
+                }
+            };
+
+            let bytes: Vec<u8> = buffer[..samples]
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes().to_vec())
+                .collect();
+            self.output.write_all(&bytes)?;
+            frames_written += (samples / CHANNELS as usize) as u64;
+            // don't get too far ahead of what's on air, transmitters that don't pace
+            // themselves would otherwise go through songs in no time
+            let written =
+                Duration::from_micros(frames_written * 1_000_000 / u64::from(SAMPLE_RATE));
+            let on_air = started_at.elapsed() + MAX_AHEAD;
+            if written > on_air {
+                thread::sleep(written - on_air);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets radio know that the source starts playing.
+    fn start(&self, source: Source, control_pipe: &mut Option<File>) -> Source {
+        self.radio.do_send(StreamEvent::Started);
+        update_radio_text(control_pipe, &source.radio_text);
+        source
+    }
+}
+
+/// Mixes the ending song with the next one, fading the ending one out.
+/// `frames_left` is number of frames the ending song had before this chunk.
+fn crossfade(current: &mut [i16], next: &[i16], frames_left: u64, crossfade_frames: u64) {
+    let channels = CHANNELS as usize;
+    for (frame, (current, next)) in current
+        .chunks_mut(channels)
+        .zip(next.chunks(channels))
+        .enumerate()
+    {
+        let left = frames_left
+            .saturating_sub(frame as u64)
+            .min(crossfade_frames);
+        let volume = left as f32 / crossfade_frames as f32;
+        for (current, next) in current.iter_mut().zip(next) {
+            *current = (f32::from(*current) * volume + f32::from(*next) * (1.0 - volume)) as i16;
+        }
+    }
+}
+
+/// Sends new radio text to the transmitter if it accepts RDS commands.
+fn update_radio_text(control_pipe: &mut Option<File>, radio_text: &str) {
+    if let Some(pipe) = control_pipe.as_mut() {
+        let _ = writeln!(pipe, "RT {}", radio_text);
+    }
+}
@@ -1,8 +1,19 @@
-use super::config::{get_config, update_config};
+use super::autoplay::AutoplayConfig;
+use super::config::{get_config, update_config, Config};
+use super::download::{cancel_download, get_downloads};
+use super::history::{get_history, PlaySource};
 use super::io::MyIO;
+use super::playlist::{
+    create_playlist, delete_playlist, get_playlist, get_playlists, update_playlist,
+};
 use super::radio::Radio;
-use super::song::{delete_song, get_all_songs, toggle_song_nsfw};
-use super::song_queue::SongQueue;
+use super::search::search_songs;
+use super::song::{delete_song, get_all_songs, normalize_songs, toggle_song_nsfw};
+use super::song_queue::{
+    get_play_mode, update_play_mode, PlayMode, QueueOrder, RequestLimits, SongQueue, Turns,
+};
+use super::timed::{cancel_timed_entry, create_timed_entry, get_timed_entries};
+use super::transmitter;
 use super::web_socket::ws_index;
 use crate::db::{new_pool, DBExecutor};
 use actix::prelude::*;
@@ -10,6 +21,7 @@ use actix::sync::SyncArbiter;
 use actix_web::fs::{NamedFile, StaticFileConfig, StaticFiles};
 use actix_web::{http, middleware, middleware::cors::Cors, server, App, HttpRequest, Result};
 use dotenv::dotenv;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::path::PathBuf;
 
@@ -18,10 +30,18 @@ pub struct AppState {
     pub queue_handler: Addr<SongQueue>,
     pub db: Addr<DBExecutor>,
     pub radio: Addr<Radio>,
+    pub io: Addr<MyIO>,
+    // searches have their own workers, so downloads never hold them up
+    pub search: Addr<MyIO>,
 }
 
 pub struct System;
 
+/// How many IO workers run downloads and loudness measurements unless IO_WORKERS says otherwise.
+const DEFAULT_IO_WORKERS: usize = 3;
+/// How many searches can run at once.
+const SEARCH_WORKERS: usize = 2;
+
 /// Serves static files inside /static/client.
 /// If requested path doesn't match any file then home page is returned.
 fn serve_files(req: &HttpRequest<AppState>) -> Result<NamedFile> {
@@ -49,14 +69,40 @@ impl System {
         let database_poll = new_pool(database_url).expect("Failed to create pool");
         let db = DBExecutor::new(database_poll.clone()).start();
         let second_db_addr = db.clone();
-        let radio = Arbiter::start(|ctx| Radio::new());
-        let io = SyncArbiter::start(1, move || MyIO { db: db.clone() });
+        let radio_db_addr = second_db_addr.clone();
+        // server works without the transmitter as well, clients are told that radio is offline
+        let radio = match transmitter::from_env() {
+            Ok(transmitter) => Arbiter::start(|_| Radio::new(transmitter, radio_db_addr)),
+            Err(reason) => Arbiter::start(|_| Radio::offline(reason, radio_db_addr)),
+        };
+        let search_db_addr = db.clone();
+        let search = SyncArbiter::start(SEARCH_WORKERS, move || MyIO {
+            db: search_db_addr.clone(),
+        });
+        let io_workers = env::var("IO_WORKERS")
+            .ok()
+            .and_then(|workers| workers.parse().ok())
+            .filter(|workers| *workers > 0)
+            .unwrap_or(DEFAULT_IO_WORKERS);
+        let io = SyncArbiter::start(io_workers, move || MyIO { db: db.clone() });
         let queue_handler = SongQueue {
             IO: io.clone(),
             db: second_db_addr.clone(),
             songs_queue: Vec::new(),
             radio: radio.clone(),
             active_song: None,
+            on_air: None,
+            order: QueueOrder::default(),
+            skip_threshold: Config::default().skip_threshold,
+            skip_votes: HashSet::new(),
+            autoplay: AutoplayConfig::default(),
+            play_mode: PlayMode::default(),
+            active_source: PlaySource::Autoplay,
+            skipped: false,
+            timed_entries: Vec::new(),
+            request_limits: RequestLimits::default(),
+            turns: Turns::default(),
+            downloading: HashMap::new(),
         }
         .start();
 
@@ -64,6 +110,8 @@ impl System {
             queue_handler,
             db: second_db_addr.clone(),
             radio,
+            io,
+            search,
         };
 
         server::new(move || {
@@ -75,12 +123,48 @@ impl System {
                         .resource("/songs", |r| {
                             r.method(http::Method::GET).with(get_all_songs)
                         })
+                        // registered before /songs/{id}, otherwise "normalize" is taken for an id
+                        .resource("/songs/normalize", |r| {
+                            r.method(http::Method::POST).with(normalize_songs)
+                        })
                         .resource("/songs/{id}", |r| {
                             r.method(http::Method::DELETE).with(delete_song)
                         })
                         .resource("/songs/{id}/{is_nsfw}", |r| {
                             r.method(http::Method::PUT).with(toggle_song_nsfw)
                         })
+                        .resource("/playlists", |r| {
+                            r.method(http::Method::GET).with(get_playlists);
+                            r.method(http::Method::POST).with(create_playlist);
+                        })
+                        .resource("/playlists/{id}", |r| {
+                            r.method(http::Method::GET).with(get_playlist);
+                            r.method(http::Method::PUT).with(update_playlist);
+                            r.method(http::Method::DELETE).with(delete_playlist);
+                        })
+                        .resource("/queue/mode", |r| {
+                            r.method(http::Method::GET).with(get_play_mode);
+                            r.method(http::Method::PUT).with(update_play_mode);
+                        })
+                        .resource("/queue/timed", |r| {
+                            r.method(http::Method::GET).with(get_timed_entries);
+                            r.method(http::Method::POST).with(create_timed_entry);
+                        })
+                        .resource("/queue/timed/{id}", |r| {
+                            r.method(http::Method::DELETE).with(cancel_timed_entry)
+                        })
+                        .resource("/downloads", |r| {
+                            r.method(http::Method::GET).with(get_downloads)
+                        })
+                        .resource("/downloads/{id}", |r| {
+                            r.method(http::Method::DELETE).with(cancel_download)
+                        })
+                        .resource("/search", |r| {
+                            r.method(http::Method::GET).with(search_songs)
+                        })
+                        .resource("/history", |r| {
+                            r.method(http::Method::GET).with(get_history)
+                        })
                         .resource("/config", |r| {
                             r.method(http::Method::PUT).with(update_config);
                             r.method(http::Method::GET).with(get_config);
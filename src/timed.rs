@@ -0,0 +1,347 @@
+use super::db::{DeleteTimedEntry, GetTimedEntries, SaveTimedEntry};
+use super::responses::{get_standard_failure_response, get_standard_success_response};
+use super::schema::timed_entries;
+use super::song_queue::ReloadTimedEntries;
+use super::system::AppState;
+use actix_web::{
+    AsyncResponder, Error as AWError, FutureResponse, HttpResponse, Json, Path, ResponseError,
+    State,
+};
+use chrono::prelude::*;
+use chrono::Duration;
+use diesel::result::Error as DieselError;
+use diesel::{Insertable, Queryable};
+use failure::Fail;
+use futures::future::Future;
+use serde::{Deserialize, Serialize};
+
+#[derive(Queryable, Clone, Debug)]
+/// Timed entry as it's saved in the database, weekdays are separated by commas.
+pub struct TimedEntryRow {
+    id: i32,
+    song_id: Option<i32>,
+    playlist_id: Option<i32>,
+    at: NaiveDateTime,
+    weekdays: Option<String>,
+    interrupt: bool,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "timed_entries"]
+pub struct NewTimedEntry {
+    pub song_id: Option<i32>,
+    pub playlist_id: Option<i32>,
+    pub at: NaiveDateTime,
+    pub weekdays: Option<String>,
+    pub interrupt: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+/// Song or playlist played at given time, dates are in local time.
+pub struct TimedEntry {
+    pub id: i32,
+    pub song_id: Option<i32>,
+    pub playlist_id: Option<i32>,
+    // when the entry is played next time
+    pub at: NaiveDateTime,
+    // entry is played every week on these days, it's played once if there are none
+    pub weekdays: Vec<Weekday>,
+    // whether the song on air is cut off, otherwise the entry is played right after it
+    pub interrupt: bool,
+}
+
+impl From<TimedEntryRow> for TimedEntry {
+    fn from(row: TimedEntryRow) -> Self {
+        let weekdays = row
+            .weekdays
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|weekday| weekday.parse().ok())
+            .collect();
+        TimedEntry {
+            id: row.id,
+            song_id: row.song_id,
+            playlist_id: row.playlist_id,
+            at: row.at,
+            weekdays,
+            interrupt: row.interrupt,
+        }
+    }
+}
+
+impl TimedEntry {
+    /// When the entry is played after given time, None if it's played only once.
+    pub fn next_occurrence(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        next_occurrence(&self.weekdays, self.at.time(), after)
+    }
+}
+
+/// First of the given weekdays at given time that comes after given time.
+fn next_occurrence(
+    weekdays: &[Weekday],
+    time: NaiveTime,
+    after: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    (0..=7)
+        .map(|days| (after.date() + Duration::days(days)).and_time(time))
+        .find(|at| *at > after && weekdays.contains(&at.weekday()))
+}
+
+#[derive(Deserialize)]
+/// Either `at` for an entry played once or `time` and `weekdays` for an entry played every week.
+pub struct TimedEntryRequest {
+    pub song_id: Option<i32>,
+    pub playlist_id: Option<i32>,
+    // local time, e.g "2026-10-19T07:00:00"
+    pub at: Option<NaiveDateTime>,
+    // HH:MM:SS
+    pub time: Option<NaiveTime>,
+    // e.g "Mon" or "monday"
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    #[serde(default)]
+    pub interrupt: bool,
+}
+
+impl TimedEntryRequest {
+    /// Entry that can be saved, reason why not otherwise.
+    pub fn into_new_entry(self, now: NaiveDateTime) -> Result<NewTimedEntry, TimedEntryError> {
+        if self.song_id.is_some() == self.playlist_id.is_some() {
+            return Err(TimedEntryError::InvalidEntry(
+                "either song or playlist has to be given".to_owned(),
+            ));
+        }
+        let (at, weekdays) = match (self.at, self.time) {
+            (Some(at), None) if self.weekdays.is_empty() => {
+                if at <= now {
+                    return Err(TimedEntryError::InvalidEntry(
+                        "time has already passed".to_owned(),
+                    ));
+                }
+                (at, None)
+            }
+            (None, Some(time)) if !self.weekdays.is_empty() => {
+                // weekdays are non empty, so there is always the next occurrence
+                let at = next_occurrence(&self.weekdays, time, now).unwrap_or(now);
+                let weekdays: Vec<String> = self
+                    .weekdays
+                    .iter()
+                    .map(|weekday| format!("{:?}", weekday))
+                    .collect();
+                (at, Some(weekdays.join(",")))
+            }
+            _ => {
+                return Err(TimedEntryError::InvalidEntry(
+                    "either at or time with weekdays has to be given".to_owned(),
+                ))
+            }
+        };
+        Ok(NewTimedEntry {
+            song_id: self.song_id,
+            playlist_id: self.playlist_id,
+            at,
+            weekdays,
+            interrupt: self.interrupt,
+        })
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum TimedEntryError {
+    #[fail(display = "timed entry doesn't exist")]
+    NotFound,
+    #[fail(display = "song {} doesn't exist", _0)]
+    UnknownSong(i32),
+    #[fail(display = "playlist {} doesn't exist", _0)]
+    UnknownPlaylist(i32),
+    #[fail(display = "invalid timed entry: {}", _0)]
+    InvalidEntry(String),
+    #[fail(display = "database error: {}", _0)]
+    Database(#[cause] DieselError),
+}
+
+impl From<DieselError> for TimedEntryError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DieselError::NotFound => TimedEntryError::NotFound,
+            error => TimedEntryError::Database(error),
+        }
+    }
+}
+
+impl ResponseError for TimedEntryError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            TimedEntryError::NotFound => HttpResponse::NotFound(),
+            TimedEntryError::UnknownSong(_)
+            | TimedEntryError::UnknownPlaylist(_)
+            | TimedEntryError::InvalidEntry(_) => HttpResponse::BadRequest(),
+            TimedEntryError::Database(_) => HttpResponse::InternalServerError(),
+        }
+        .json(get_standard_failure_response())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TimedEntryId {
+    id: i32,
+}
+
+// API functions
+/// GET /queue/timed
+pub fn get_timed_entries(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetTimedEntries {})
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(|entries| HttpResponse::Ok().json(entries))
+        .responder()
+}
+
+/// POST /queue/timed
+pub fn create_timed_entry(
+    request: Json<TimedEntryRequest>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    let queue_handler = state.queue_handler.clone();
+    let entry = match request
+        .into_inner()
+        .into_new_entry(Local::now().naive_local())
+    {
+        Ok(entry) => entry,
+        Err(e) => return Box::new(futures::future::err(AWError::from(e))),
+    };
+    state
+        .db
+        .send(SaveTimedEntry { entry })
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(move |entry| {
+            queue_handler.do_send(ReloadTimedEntries {});
+            HttpResponse::Ok().json(entry)
+        })
+        .responder()
+}
+
+/// DELETE /queue/timed/{id}
+pub fn cancel_timed_entry(
+    path: Path<TimedEntryId>,
+    state: State<AppState>,
+) -> FutureResponse<HttpResponse> {
+    let queue_handler = state.queue_handler.clone();
+    state
+        .db
+        .send(DeleteTimedEntry { id: path.id })
+        .from_err()
+        .and_then(|res| res.map_err(AWError::from))
+        .map(move |_| {
+            queue_handler.do_send(ReloadTimedEntries {});
+            HttpResponse::Ok().json(get_standard_success_response())
+        })
+        .responder()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        // 2026-10-19 is monday
+        NaiveDate::from_ymd(2026, 10, day).and_hms(hour, 0, 0)
+    }
+
+    fn request(value: serde_json::Value) -> TimedEntryRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn is_invalid(result: Result<NewTimedEntry, TimedEntryError>) -> bool {
+        matches!(result, Err(TimedEntryError::InvalidEntry(_)))
+    }
+
+    #[test]
+    fn next_occurrence_is_later_the_same_day() {
+        let time = NaiveTime::from_hms(9, 0, 0);
+        assert_eq!(
+            next_occurrence(&[Weekday::Mon], time, at(19, 8)),
+            Some(at(19, 9))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_is_next_week_if_time_already_passed() {
+        let time = NaiveTime::from_hms(7, 0, 0);
+        assert_eq!(
+            next_occurrence(&[Weekday::Mon], time, at(19, 8)),
+            Some(at(26, 7))
+        );
+        // entry that was just played isn't played again right away
+        assert_eq!(
+            next_occurrence(&[Weekday::Mon], time, at(19, 7)),
+            Some(at(26, 7))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_wraps_around_the_week() {
+        let time = NaiveTime::from_hms(7, 0, 0);
+        let weekdays = [Weekday::Mon, Weekday::Wed];
+        assert_eq!(
+            next_occurrence(&weekdays, time, at(24, 10)),
+            Some(at(26, 7))
+        );
+        assert_eq!(
+            next_occurrence(&weekdays, time, at(26, 10)),
+            Some(at(28, 7))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_of_entry_played_once_is_none() {
+        let time = NaiveTime::from_hms(7, 0, 0);
+        assert_eq!(next_occurrence(&[], time, at(19, 8)), None);
+    }
+
+    #[test]
+    fn entry_played_once_has_to_be_in_future() {
+        let entry = request(json!({"song_id": 1, "at": "2026-10-20T07:00:00"}))
+            .into_new_entry(at(19, 8))
+            .unwrap();
+        assert_eq!(entry.at, at(20, 7));
+        assert_eq!(entry.weekdays, None);
+        let passed = request(json!({"song_id": 1, "at": "2026-10-19T07:00:00"}));
+        assert!(is_invalid(passed.into_new_entry(at(19, 8))));
+    }
+
+    #[test]
+    fn recurring_entry_starts_at_next_occurrence() {
+        let entry = request(json!({"playlist_id": 1, "time": "07:00:00", "weekdays": ["Mon", "friday"], "interrupt": true}))
+            .into_new_entry(at(19, 8))
+            .unwrap();
+        assert_eq!(entry.at, at(23, 7));
+        assert_eq!(entry.weekdays, Some("Mon,Fri".to_owned()));
+        assert!(entry.interrupt);
+    }
+
+    #[test]
+    fn entry_needs_either_song_or_playlist() {
+        let both = request(json!({"song_id": 1, "playlist_id": 1, "at": "2026-10-20T07:00:00"}));
+        assert!(is_invalid(both.into_new_entry(at(19, 8))));
+        let neither = request(json!({"at": "2026-10-20T07:00:00"}));
+        assert!(is_invalid(neither.into_new_entry(at(19, 8))));
+    }
+
+    #[test]
+    fn entry_needs_either_at_or_time_with_weekdays() {
+        let time_only = request(json!({"song_id": 1, "time": "07:00:00"}));
+        assert!(is_invalid(time_only.into_new_entry(at(19, 8))));
+        let at_with_weekdays =
+            request(json!({"song_id": 1, "at": "2026-10-20T07:00:00", "weekdays": ["Mon"]}));
+        assert!(is_invalid(at_with_weekdays.into_new_entry(at(19, 8))));
+        let both = request(
+            json!({"song_id": 1, "at": "2026-10-20T07:00:00", "time": "07:00:00", "weekdays": ["Mon"]}),
+        );
+        assert!(is_invalid(both.into_new_entry(at(19, 8))));
+    }
+}
@@ -0,0 +1,314 @@
+use std::env;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Exit code of `timeout` when it stopped the program, which is how timed transmissions end.
+const TIMED_OUT: i32 = 124;
+/// How many bytes from the end of transmitter's stderr are kept in error reports.
+pub const STDERR_LIMIT: usize = 1024;
+
+/// Everything backend needs to know in order to put a song on air.
+pub struct Transmission<'a> {
+    /// Path to the wav file, "-" if audio is piped into stdin.
+    pub audio: &'a str,
+    /// Frequency in MHz.
+    pub frequency: f32,
+    /// How long the transmission lasts in seconds,
+    /// None if it lasts until the audio piped into stdin is over.
+    pub duration: Option<i32>,
+    pub rds: Rds<'a>,
+}
+
+/// Data broadcasted via RDS, so receivers can display what's on air.
+pub struct Rds<'a> {
+    pub station_name: &'a str,
+    /// Programme identification code.
+    pub pi_code: &'a str,
+    pub radio_text: String,
+}
+
+/// Backend turning audio into radio waves.
+/// Radio only spawns and cancels the command returned from here, so it doesn't care
+/// which program actually transmits the song.
+pub trait Transmitter: Send {
+    /// Name of the backend, used in logs.
+    fn name(&self) -> &'static str;
+    /// Command transmitting given audio. It should exit once the song is over.
+    fn command(&self, transmission: &Transmission) -> Command;
+    /// Whether the backend reads the audio at all.
+    fn needs_audio(&self) -> bool {
+        true
+    }
+    /// Named pipe accepting RDS commands (e.g "RT <text>") during the transmission.
+    fn control_pipe(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Whether transmitter stopped because the song was over rather than because it failed.
+pub fn is_normal_exit(status: ExitStatus) -> bool {
+    status.success() || status.code() == Some(TIMED_OUT)
+}
+
+/// Describes how transmitter stopped, along with the end of what it wrote to stderr.
+pub fn describe_exit(status: ExitStatus, stderr: &[u8]) -> String {
+    let stderr = &stderr[stderr.len().saturating_sub(STDERR_LIMIT)..];
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        format!("transmitter stopped with {}", status)
+    } else {
+        format!("transmitter stopped with {} - {}", status, stderr)
+    }
+}
+
+/// Command running given program, killed after given amount of seconds if there's any.
+fn timed(duration: Option<i32>, program: &str) -> Command {
+    match duration {
+        Some(duration) => {
+            let mut command = Command::new("timeout");
+            command.arg(duration.to_string()).arg(program);
+            command
+        }
+        None => Command::new(program),
+    }
+}
+
+/// How the transmitter's binary is run.
+pub struct Invocation {
+    binary_path: String,
+    /// Whether the binary is run with sudo, it's usually needed to access the hardware.
+    sudo: bool,
+    /// Extra arguments passed to the binary before the ones describing the transmission.
+    args: Vec<String>,
+}
+
+impl Invocation {
+    /// Reads invocation from TRANSMITTER_PATH (given default path if missing),
+    /// TRANSMITTER_SUDO (true if missing) and TRANSMITTER_ARGS (separated with whitespace).
+    /// Returns reason why the binary can't be run otherwise.
+    pub fn from_env(default_path: &str) -> Result<Self, String> {
+        let path = env::var("TRANSMITTER_PATH").unwrap_or_else(|_| default_path.to_owned());
+        let binary_path =
+            get_binary_path(&path).map_err(|_| format!("there's no binary at {}", path))?;
+        let sudo = env::var("TRANSMITTER_SUDO")
+            .map(|sudo| sudo != "false" && sudo != "0")
+            .unwrap_or(true);
+        let args = env::var("TRANSMITTER_ARGS")
+            .map(|args| args.split_whitespace().map(str::to_owned).collect())
+            .unwrap_or_default();
+        Ok(Invocation {
+            binary_path,
+            sudo,
+            args,
+        })
+    }
+
+    /// Command running the binary, killed after given amount of seconds if there's any.
+    fn command(&self, duration: Option<i32>) -> Command {
+        let mut command = if self.sudo {
+            let mut command = timed(duration, "sudo");
+            command.arg(&self.binary_path);
+            command
+        } else {
+            timed(duration, &self.binary_path)
+        };
+        command.args(&self.args);
+        command
+    }
+}
+
+/// Transmits songs via [PiFmAdv](https://github.com/miegl/PiFmAdv).
+pub struct PiFmAdv {
+    invocation: Invocation,
+    control_pipe: Option<String>,
+}
+
+impl PiFmAdv {
+    pub fn new(invocation: Invocation) -> Self {
+        let control_pipe = create_control_pipe("pi_fm_adv.ctl");
+        PiFmAdv {
+            invocation,
+            control_pipe,
+        }
+    }
+}
+
+impl Transmitter for PiFmAdv {
+    fn name(&self) -> &'static str {
+        "pi_fm_adv"
+    }
+
+    fn command(&self, transmission: &Transmission) -> Command {
+        let mut command = self.invocation.command(transmission.duration);
+        command
+            .arg("--freq")
+            // replace . with , because that's what library
+            .arg(transmission.frequency.to_string().replace(".", ","))
+            .arg("--audio")
+            .arg(transmission.audio)
+            .arg("--ps")
+            .arg(transmission.rds.station_name)
+            .arg("--pi")
+            .arg(transmission.rds.pi_code)
+            .arg("--rt")
+            .arg(&transmission.rds.radio_text);
+        if let Some(control_pipe) = self.control_pipe.as_ref() {
+            command.arg("--ctl").arg(control_pipe);
+        }
+        command
+    }
+
+    fn control_pipe(&self) -> Option<&str> {
+        self.control_pipe.as_deref()
+    }
+}
+
+/// Transmits songs via [fm_transmitter](https://github.com/somu1795/fm_transmitter).
+pub struct FmTransmitter {
+    invocation: Invocation,
+}
+
+impl FmTransmitter {
+    pub fn new(invocation: Invocation) -> Self {
+        FmTransmitter { invocation }
+    }
+}
+
+impl Transmitter for FmTransmitter {
+    fn name(&self) -> &'static str {
+        "fm_transmitter"
+    }
+
+    fn command(&self, transmission: &Transmission) -> Command {
+        let mut command = self.invocation.command(transmission.duration);
+        command
+            .arg("-f")
+            .arg(transmission.frequency.to_string())
+            .arg(transmission.audio);
+        command
+    }
+}
+
+/// Doesn't transmit anything, it only waits until the song would be over
+/// or throws away the audio piped into it.
+/// Useful for running the server on machines without any radio hardware.
+pub struct NullTransmitter;
+
+impl Transmitter for NullTransmitter {
+    fn name(&self) -> &'static str {
+        "null"
+    }
+
+    fn command(&self, transmission: &Transmission) -> Command {
+        match transmission.duration {
+            Some(duration) => {
+                let mut command = Command::new("sleep");
+                command.arg(duration.to_string());
+                command
+            }
+            None => {
+                let mut command = Command::new("cat");
+                command.stdout(Stdio::null());
+                command
+            }
+        }
+    }
+
+    fn needs_audio(&self) -> bool {
+        false
+    }
+}
+
+/// Copies every transmitted song into a file and waits until the song would be over.
+pub struct FileSink {
+    path: String,
+}
+
+impl FileSink {
+    pub fn new(path: String) -> Self {
+        FileSink { path }
+    }
+}
+
+impl Transmitter for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn command(&self, transmission: &Transmission) -> Command {
+        let mut command = timed(transmission.duration, "sh");
+        command.arg("-c");
+        match transmission.duration {
+            Some(duration) => command
+                .arg("cat \"$0\" > \"$1\" && sleep \"$2\"")
+                .arg(transmission.audio)
+                .arg(&self.path)
+                .arg(duration.to_string()),
+            None => command
+                .arg("cat \"$0\" > \"$1\"")
+                .arg(transmission.audio)
+                .arg(&self.path),
+        };
+        command
+    }
+}
+
+/// Creates transmitter selected with TRANSMITTER env variable.
+/// Available values are pi_fm_adv (default), fm_transmitter, null and file.
+/// Binaries of pi_fm_adv and fm_transmitter are run as described by `Invocation::from_env`,
+/// file sink writes to the path from TRANSMITTER_SINK.
+/// Returns reason why selected transmitter isn't available otherwise.
+pub fn from_env() -> Result<Box<dyn Transmitter>, String> {
+    let name = env::var("TRANSMITTER").unwrap_or_else(|_| "pi_fm_adv".to_owned());
+    match name.as_str() {
+        "pi_fm_adv" => {
+            let invocation = Invocation::from_env("../PiFmAdv/src/pi_fm_adv")?;
+            Ok(Box::new(PiFmAdv::new(invocation)))
+        }
+        "fm_transmitter" => {
+            let invocation = Invocation::from_env("../fm_transmitter/fm_transmitter")?;
+            Ok(Box::new(FmTransmitter::new(invocation)))
+        }
+        "null" => Ok(Box::new(NullTransmitter)),
+        "file" => {
+            let path = env::var("TRANSMITTER_SINK")
+                .map_err(|_| "TRANSMITTER_SINK must be set".to_owned())?;
+            Ok(Box::new(FileSink::new(path)))
+        }
+        _ => Err(format!("unknown transmitter {}", name)),
+    }
+}
+
+/// Creates named pipe with given name in temp directory, unless it already exists.
+/// Returns None if the pipe couldn't be created.
+fn create_control_pipe(name: &str) -> Option<String> {
+    let path = env::temp_dir().join(name);
+    let path = path.to_str()?.to_owned();
+    let pipe_exists = Path::new(&path).exists()
+        || Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+    if pipe_exists {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Check if transmitter's binary exists and return its canonicalized path.
+fn get_binary_path(path: &str) -> Result<String, ()> {
+    let path = Path::new(path);
+    let binary_exists = path.exists();
+    if binary_exists {
+        Ok(std::fs::canonicalize(path)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned())
+    } else {
+        Err(())
+    }
+}
@@ -1,7 +1,9 @@
 use self::actix::*;
 use crate::client_publisher::{ClientPublisher, DeleteWS, RegisterWS};
+use crate::download::{self, Downloads};
+use crate::search::{SearchFailed, SearchQuery, SearchResults};
 use crate::song::SongRequest;
-use crate::song_queue::{BroadcastState, QueueJob};
+use crate::song_queue::{BroadcastState, PlayModeUpdate, QueueJob, SetPlayMode};
 use crate::system::AppState;
 use actix_web::*;
 use futures::future::Future;
@@ -17,6 +19,8 @@ pub fn ws_index(r: &HttpRequest<AppState>) -> Result<HttpResponse, Error> {
 #[derive(Debug)]
 pub struct MyWebSocket {
     hb: Instant,
+    // identifies the connection, clients behind the same address still get a vote each
+    id: Uuid,
 }
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -41,12 +45,18 @@ impl Actor for MyWebSocket {
         ClientPublisher::from_registry().do_send(DeleteWS {
             ws_addr: ctx.address(),
         });
+        ctx.state()
+            .queue_handler
+            .do_send(QueueJob::WithdrawSkipVote { voter: self.id });
     }
 }
 
 impl MyWebSocket {
     pub fn new() -> Self {
-        MyWebSocket { hb: Instant::now() }
+        MyWebSocket {
+            hb: Instant::now(),
+            id: Uuid::new_v4(),
+        }
     }
 
     /// Send message to the client
@@ -67,6 +77,11 @@ impl MyWebSocket {
         }
     }
 
+    /// Address identifying the client, e.g when it requests songs or votes.
+    fn client_address(&self, ctx: &mut <Self as Actor>::Context) -> Option<String> {
+        ctx.request().peer_addr().map(|addr| addr.ip().to_string())
+    }
+
     fn hb(&self, ctx: &mut <Self as Actor>::Context) {
         ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
             // check client heartbeats
@@ -116,6 +131,34 @@ pub struct DeleteSongFromQueue {
     uuid: Uuid,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MoveSongInQueue {
+    uuid: Uuid,
+    index: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlayNext {
+    uuid: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnqueuePlaylist {
+    id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CancelDownload {
+    id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VoteSong {
+    uuid: Uuid,
+    // 1 for upvote, -1 for downvote, 0 takes the vote back
+    vote: i32,
+}
+
 /// Handler for ws::Message message
 impl StreamHandler<ws::Message, ws::ProtocolError> for MyWebSocket {
     // It handles all of the data sent by client's via websockets.
@@ -133,9 +176,12 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for MyWebSocket {
                 match request.action.as_str() {
                     "request_song" => {
                         let song = serde_json::from_str::<Payload<SongRequest>>(&text);
-                        let response = if let Ok(song) = song {
+                        let song = song.ok().filter(|song| song.payload.is_valid());
+                        let response = if let Some(mut song) = song {
+                            song.payload.requested_by = self.client_address(ctx);
                             ctx.state().queue_handler.do_send(QueueJob::DownloadSong {
                                 requested_song: song.payload,
+                                requester: ctx.address(),
                             });
                             UserMessage::<EmptyValue> {
                                 success: true,
@@ -152,7 +198,165 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for MyWebSocket {
                         self.send_message(ctx, &response);
                     }
                     "skip_song" => {
-                        ctx.state().queue_handler.do_send(QueueJob::SkipSong {});
+                        // song is skipped once enough connections want to skip it, every one of them gets a vote
+                        ctx.state()
+                            .queue_handler
+                            .do_send(QueueJob::VoteSkip { voter: self.id });
+                    }
+                    "vote_song" => {
+                        let vote = serde_json::from_str::<Payload<VoteSong>>(&text);
+                        if let Ok(vote) = vote {
+                            // every connection gets a vote, even if clients share an address
+                            ctx.state().queue_handler.do_send(QueueJob::VoteSong {
+                                uuid: vote.payload.uuid,
+                                voter: self.id,
+                                vote: vote.payload.vote,
+                            });
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
+                    }
+                    "pause_song" => {
+                        ctx.state().queue_handler.do_send(QueueJob::PauseSong {});
+                    }
+                    "resume_song" => {
+                        ctx.state().queue_handler.do_send(QueueJob::ResumeSong {});
+                    }
+                    "move_song_in_queue" => {
+                        let song = serde_json::from_str::<Payload<MoveSongInQueue>>(&text);
+                        if let Ok(song) = song {
+                            ctx.state()
+                                .queue_handler
+                                .do_send(QueueJob::MoveSongInQueue {
+                                    uuid: song.payload.uuid,
+                                    index: song.payload.index,
+                                });
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
+                    }
+                    "play_next" => {
+                        let song = serde_json::from_str::<Payload<PlayNext>>(&text);
+                        if let Ok(song) = song {
+                            // song played next is simply moved to the front of the queue
+                            ctx.state()
+                                .queue_handler
+                                .do_send(QueueJob::MoveSongInQueue {
+                                    uuid: song.payload.uuid,
+                                    index: 0,
+                                });
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
+                    }
+                    "enqueue_playlist" => {
+                        let playlist = serde_json::from_str::<Payload<EnqueuePlaylist>>(&text);
+                        if let Ok(playlist) = playlist {
+                            let requested_by = self.client_address(ctx);
+                            ctx.state()
+                                .queue_handler
+                                .do_send(QueueJob::EnqueuePlaylist {
+                                    playlist_id: playlist.payload.id,
+                                    requested_by,
+                                });
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
+                    }
+                    "set_play_mode" => {
+                        let update = serde_json::from_str::<Payload<PlayModeUpdate>>(&text);
+                        if let Ok(update) = update {
+                            ctx.state().queue_handler.do_send(SetPlayMode {
+                                update: update.payload,
+                            });
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
+                    }
+                    "cancel_download" => {
+                        let download = serde_json::from_str::<Payload<CancelDownload>>(&text);
+                        if let Ok(download) = download {
+                            Downloads::from_registry().do_send(download::CancelDownload {
+                                id: download.payload.id,
+                            });
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
+                    }
+                    "search_songs" => {
+                        let search = serde_json::from_str::<Payload<SearchQuery>>(&text)
+                            .ok()
+                            .and_then(|search| search.payload.into_search().ok());
+                        if let Some(search) = search {
+                            // results are sent only to the client that searched for them
+                            let query = search.query.clone();
+                            let future = ctx
+                                .state()
+                                .search
+                                .send(search)
+                                .into_actor(self)
+                                .map(move |results, act, ctx| match results {
+                                    Ok(results) => {
+                                        let response = UserMessage::<SearchResults> {
+                                            success: true,
+                                            action: "search_results".to_owned(),
+                                            value: SearchResults { query, results },
+                                        };
+                                        act.send_message(ctx, &response);
+                                    }
+                                    Err(error) => {
+                                        let response = UserMessage::<SearchFailed> {
+                                            success: false,
+                                            action: "search_failed".to_owned(),
+                                            value: SearchFailed {
+                                                query,
+                                                reason: error.reason(),
+                                                message: error.to_string(),
+                                            },
+                                        };
+                                        act.send_message(ctx, &response);
+                                    }
+                                })
+                                .map_err(|e, _act, _ctx| println!("io crashed - {}", e));
+                            ctx.spawn(future);
+                        } else {
+                            let response = UserMessage::<EmptyValue> {
+                                success: true,
+                                action: "incomplete_data".to_owned(),
+                                value: EmptyValue {},
+                            };
+                            self.send_message(ctx, &response);
+                        }
                     }
                     "delete_song_from_queue" => {
                         let song_uuid = serde_json::from_str::<Payload<DeleteSongFromQueue>>(&text);